@@ -0,0 +1,473 @@
+use crate::error::{Result, SwagitError};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// A local branch as read from the repository.
+#[derive(Debug)]
+pub struct BranchRecord {
+  pub name: String,
+  pub commit_id: String,
+  pub commit_time: i64, // committer date, seconds since the epoch
+  pub author: String,
+  pub subject: String,
+  pub upstream: Option<String>,
+}
+
+/// Low-level git operations used by `GitManager`.
+///
+/// The libgit2 implementation is used whenever the repository can be opened
+/// in-process; the CLI implementation shells out to `git` and acts as the
+/// fallback (or can be forced with `SWAGIT_BACKEND=cli`). Both write the
+/// working tree through `git`, so filters and hooks run as usual.
+pub trait GitBackend {
+  /// Name of the checked out branch, or an empty string on a detached HEAD.
+  fn current_branch(&self) -> Result<String>;
+
+  /// All local branches, sorted by name.
+  fn local_branches(&self) -> Result<Vec<BranchRecord>>;
+
+  /// All remote-tracking branches except `<remote>/HEAD`, named like
+  /// `origin/feature` and sorted by name.
+  fn remote_branches(&self) -> Result<Vec<BranchRecord>>;
+
+  /// Creates `branch` at `upstream` (e.g. `origin/feature`) and tracks it.
+  fn create_tracking_branch(&self, branch: &str, upstream: &str) -> Result<()>;
+
+  /// Creates `branch` at `start` (any revision) without an upstream.
+  fn create_branch(&self, branch: &str, start: &str) -> Result<()>;
+
+  /// Messages of the `HEAD` reflog, newest first.
+  fn head_reflog(&self) -> Result<Vec<String>>;
+
+  fn checkout(&self, branch: &str) -> Result<()>;
+
+  /// Deletes a local branch regardless of its merge state.
+  fn delete_branch(&self, branch: &str) -> Result<()>;
+
+  /// Moves `branch` to `target` without touching any working tree. Callers
+  /// make sure this is a fast-forward.
+  fn fast_forward(&self, branch: &str, target: &str) -> Result<()>;
+
+  /// Local branches whose tip is reachable from `target`.
+  fn merged_branches(&self, target: &str) -> Result<Vec<String>>;
+
+  fn ref_exists(&self, refname: &str) -> bool;
+
+  /// Whether `ancestor` is reachable from `descendant` (or the same commit).
+  fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool>;
+
+  /// Number of commits `local` is ahead of and behind `upstream`.
+  fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)>;
+
+  /// Best common ancestor of `one` and `two`, `None` for unrelated histories.
+  fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>>;
+}
+
+/// Picks the backend for the repository in the current directory.
+pub fn open() -> Result<Box<dyn GitBackend>> {
+  let cli_only = std::env::var("SWAGIT_BACKEND")
+    .map(|backend| backend == "cli")
+    .unwrap_or(false);
+
+  if !cli_only {
+    if let Ok(backend) = Git2Backend::open() {
+      return Ok(Box::new(backend));
+    }
+  }
+
+  Ok(Box::new(CliBackend::open()?))
+}
+
+pub fn run_git(cmd: &str, args: &[&str]) -> Result<String> {
+  run_git_in(".", cmd, args)
+}
+
+/// Runs `git` as if started in `dir`.
+pub fn run_git_in(dir: &str, cmd: &str, args: &[&str]) -> Result<String> {
+  let output = Command::new("git")
+    .arg("-C")
+    .arg(dir)
+    .arg(cmd)
+    .args(args)
+    .output()?;
+
+  if output.status.success() {
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+  } else {
+    let error = String::from_utf8_lossy(&output.stderr);
+    Err(SwagitError::from_git_output(output.status.code(), &error))
+  }
+}
+
+/// Runs `git` with `input` on its stdin.
+pub fn run_git_with_input(cmd: &str, args: &[&str], input: &str) -> Result<String> {
+  let mut child = Command::new("git")
+    .arg(cmd)
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+
+  // Written from another thread so a full stdout pipe can't block us
+  let mut stdin = child.stdin.take();
+  let input = input.to_string();
+  let writer = std::thread::spawn(move || match stdin.as_mut() {
+    Some(stdin) => stdin.write_all(input.as_bytes()),
+    None => Ok(()),
+  });
+  let output = child.wait_with_output()?;
+  writer.join().ok();
+
+  if output.status.success() {
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+  } else {
+    let error = String::from_utf8_lossy(&output.stderr);
+    Err(SwagitError::from_git_output(output.status.code(), &error))
+  }
+}
+
+pub struct CliBackend;
+
+impl CliBackend {
+  pub fn open() -> Result<Self> {
+    run_git("rev-parse", &["--git-dir"])?;
+    Ok(Self)
+  }
+}
+
+impl CliBackend {
+  fn branch_records(&self, prefix: &str) -> Result<Vec<BranchRecord>> {
+    let output = run_git(
+      "for-each-ref",
+      &[
+        "--format=%(refname)%00%(objectname:short)%00%(committerdate:unix)%00%(authorname)%00%(upstream:short)%00%(contents:subject)",
+        prefix,
+      ],
+    )?;
+
+    Ok(
+      output
+        .lines()
+        .filter_map(|line| {
+          let parts: Vec<&str> = line.split('\0').collect();
+          match parts.as_slice() {
+            [refname, commit_id, time, author, upstream, subject] => Some(BranchRecord {
+              name: refname.strip_prefix(prefix)?.to_string(),
+              commit_id: commit_id.to_string(),
+              commit_time: time.parse().unwrap_or(0),
+              author: author.to_string(),
+              subject: subject.to_string(),
+              upstream: Some(upstream.to_string()).filter(|u| !u.is_empty()),
+            }),
+            _ => None,
+          }
+        })
+        .collect(),
+    )
+  }
+}
+
+impl GitBackend for CliBackend {
+  fn current_branch(&self) -> Result<String> {
+    Ok(run_git("branch", &["--show-current"])?.trim().to_string())
+  }
+
+  fn local_branches(&self) -> Result<Vec<BranchRecord>> {
+    self.branch_records("refs/heads/")
+  }
+
+  fn remote_branches(&self) -> Result<Vec<BranchRecord>> {
+    Ok(
+      self
+        .branch_records("refs/remotes/")?
+        .into_iter()
+        .filter(|branch| !branch.name.ends_with("/HEAD"))
+        .collect(),
+    )
+  }
+
+  fn create_tracking_branch(&self, branch: &str, upstream: &str) -> Result<()> {
+    run_git("branch", &["--track", branch, upstream])?;
+    Ok(())
+  }
+
+  fn create_branch(&self, branch: &str, start: &str) -> Result<()> {
+    run_git("branch", &["--no-track", branch, start])?;
+    Ok(())
+  }
+
+  fn head_reflog(&self) -> Result<Vec<String>> {
+    let output = run_git("log", &["--walk-reflogs", "--format=%gs", "HEAD"])?;
+    Ok(output.lines().map(|line| line.to_string()).collect())
+  }
+
+  fn checkout(&self, branch: &str) -> Result<()> {
+    run_git("checkout", &[branch])?;
+    Ok(())
+  }
+
+  fn delete_branch(&self, branch: &str) -> Result<()> {
+    run_git("branch", &["-D", branch])?;
+    Ok(())
+  }
+
+  fn fast_forward(&self, branch: &str, target: &str) -> Result<()> {
+    let refname = format!("refs/heads/{}", branch);
+    let old = run_git("rev-parse", &["--verify", &refname])?;
+    let new = run_git(
+      "rev-parse",
+      &["--verify", &format!("{}^{{commit}}", target)],
+    )?;
+    run_git(
+      "update-ref",
+      &[
+        "-m",
+        "swagit: fast-forward",
+        &refname,
+        new.trim(),
+        old.trim(),
+      ],
+    )?;
+    Ok(())
+  }
+
+  fn merged_branches(&self, target: &str) -> Result<Vec<String>> {
+    let output = run_git(
+      "for-each-ref",
+      &[
+        "--format=%(refname:lstrip=2)",
+        "--merged",
+        target,
+        "refs/heads/",
+      ],
+    )?;
+    Ok(output.lines().map(|line| line.trim().to_string()).collect())
+  }
+
+  fn ref_exists(&self, refname: &str) -> bool {
+    run_git("rev-parse", &["--verify", "--quiet", refname]).is_ok()
+  }
+
+  fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+    match run_git("merge-base", &["--is-ancestor", ancestor, descendant]) {
+      Ok(_) => Ok(true),
+      Err(SwagitError::GitCommand { code: Some(1), .. }) => Ok(false),
+      Err(err) => Err(err),
+    }
+  }
+
+  fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)> {
+    let output = run_git(
+      "rev-list",
+      &[
+        "--left-right",
+        "--count",
+        &format!("{}...{}", local, upstream),
+      ],
+    )?;
+
+    let counts: Vec<&str> = output.split_whitespace().collect();
+    match counts.as_slice() {
+      [ahead, behind] => match (ahead.parse(), behind.parse()) {
+        (Ok(ahead), Ok(behind)) => Ok((ahead, behind)),
+        _ => Err(SwagitError::UnexpectedOutput(output.trim().to_string())),
+      },
+      _ => Err(SwagitError::UnexpectedOutput(output.trim().to_string())),
+    }
+  }
+
+  fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>> {
+    match run_git("merge-base", &[one, two]) {
+      Ok(merge_base) => Ok(Some(merge_base.trim().to_string())),
+      Err(SwagitError::GitCommand { code: Some(1), .. }) => Ok(None),
+      Err(err) => Err(err),
+    }
+  }
+}
+
+pub struct Git2Backend {
+  repo: git2::Repository,
+}
+
+impl Git2Backend {
+  pub fn open() -> Result<Self> {
+    Ok(Self {
+      repo: git2::Repository::open_from_env()?,
+    })
+  }
+
+  fn branch_records(&self, kind: git2::BranchType) -> Result<Vec<BranchRecord>> {
+    let mut branches = Vec::new();
+    for entry in self.repo.branches(Some(kind))? {
+      let (branch, _) = entry?;
+      // Skip `<remote>/HEAD`
+      if branch.get().symbolic_target().is_some() {
+        continue;
+      }
+      let name = match branch.name()? {
+        Some(name) => name.to_string(),
+        None => continue,
+      };
+      let commit = branch.get().peel_to_commit()?;
+      let commit_id = commit
+        .as_object()
+        .short_id()?
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+      // Read from the branch config like `%(upstream:short)`, so an upstream
+      // that was pruned is still reported
+      let upstream = branch
+        .get()
+        .name()
+        .and_then(|refname| self.repo.branch_upstream_name(refname).ok())
+        .and_then(|upstream| upstream.as_str().map(shorten_refname));
+
+      branches.push(BranchRecord {
+        name,
+        commit_id,
+        commit_time: commit.committer().when().seconds(),
+        author: commit.author().name().unwrap_or_default().to_string(),
+        subject: commit.summary().unwrap_or_default().to_string(),
+        upstream,
+      });
+    }
+
+    branches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(branches)
+  }
+}
+
+/// `refs/remotes/origin/main` as `origin/main` and `refs/heads/main` as
+/// `main`.
+fn shorten_refname(refname: &str) -> String {
+  refname
+    .strip_prefix("refs/remotes/")
+    .or_else(|| refname.strip_prefix("refs/heads/"))
+    .unwrap_or(refname)
+    .to_string()
+}
+
+impl GitBackend for Git2Backend {
+  fn current_branch(&self) -> Result<String> {
+    // Read HEAD directly so unborn branches still report their name
+    let head = self.repo.find_reference("HEAD")?;
+    Ok(
+      head
+        .symbolic_target()
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .unwrap_or("")
+        .to_string(),
+    )
+  }
+
+  fn local_branches(&self) -> Result<Vec<BranchRecord>> {
+    self.branch_records(git2::BranchType::Local)
+  }
+
+  fn remote_branches(&self) -> Result<Vec<BranchRecord>> {
+    self.branch_records(git2::BranchType::Remote)
+  }
+
+  fn create_tracking_branch(&self, branch: &str, upstream: &str) -> Result<()> {
+    let target = self
+      .repo
+      .find_branch(upstream, git2::BranchType::Remote)?
+      .get()
+      .peel_to_commit()?;
+    let mut created = self.repo.branch(branch, &target, false)?;
+    created.set_upstream(Some(upstream))?;
+    Ok(())
+  }
+
+  fn create_branch(&self, branch: &str, start: &str) -> Result<()> {
+    let target = self.repo.revparse_single(start)?.peel_to_commit()?;
+    self.repo.branch(branch, &target, false)?;
+    Ok(())
+  }
+
+  fn head_reflog(&self) -> Result<Vec<String>> {
+    let reflog = self.repo.reflog("HEAD")?;
+    Ok(
+      reflog
+        .iter()
+        .filter_map(|entry| entry.message().map(|m| m.to_string()))
+        .collect(),
+    )
+  }
+
+  // libgit2 runs neither filter drivers (e.g. git-lfs smudge) nor hooks, so
+  // the working tree is left to `git`
+  fn checkout(&self, branch: &str) -> Result<()> {
+    CliBackend.checkout(branch)
+  }
+
+  fn delete_branch(&self, branch: &str) -> Result<()> {
+    self
+      .repo
+      .find_branch(branch, git2::BranchType::Local)?
+      .delete()?;
+    Ok(())
+  }
+
+  fn fast_forward(&self, branch: &str, target: &str) -> Result<()> {
+    let refname = format!("refs/heads/{}", branch);
+    let old = self.repo.refname_to_id(&refname)?;
+    let target = self.repo.revparse_single(target)?.peel_to_commit()?.id();
+    // Fails like `git update-ref <new> <old>` when the branch moved meanwhile
+    self
+      .repo
+      .reference_matching(&refname, target, true, old, "swagit: fast-forward")?;
+    Ok(())
+  }
+
+  fn merged_branches(&self, target: &str) -> Result<Vec<String>> {
+    let head = self.repo.revparse_single(target)?.peel_to_commit()?.id();
+    let mut merged = Vec::new();
+
+    for entry in self.repo.branches(Some(git2::BranchType::Local))? {
+      let (branch, _) = entry?;
+      let tip = branch.get().peel_to_commit()?.id();
+      if tip == head || self.repo.graph_descendant_of(head, tip)? {
+        if let Some(name) = branch.name()? {
+          merged.push(name.to_string());
+        }
+      }
+    }
+
+    merged.sort();
+
+    Ok(merged)
+  }
+
+  fn ref_exists(&self, refname: &str) -> bool {
+    self.repo.revparse_single(refname).is_ok()
+  }
+
+  fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+    let ancestor = self.repo.revparse_single(ancestor)?.peel_to_commit()?.id();
+    let descendant = self
+      .repo
+      .revparse_single(descendant)?
+      .peel_to_commit()?
+      .id();
+    Ok(ancestor == descendant || self.repo.graph_descendant_of(descendant, ancestor)?)
+  }
+
+  fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)> {
+    let local = self.repo.revparse_single(local)?.peel_to_commit()?.id();
+    let upstream = self.repo.revparse_single(upstream)?.peel_to_commit()?.id();
+    Ok(self.repo.graph_ahead_behind(local, upstream)?)
+  }
+
+  fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>> {
+    let one = self.repo.revparse_single(one)?.peel_to_commit()?.id();
+    let two = self.repo.revparse_single(two)?.peel_to_commit()?.id();
+    match self.repo.merge_base(one, two) {
+      Ok(merge_base) => Ok(Some(merge_base.to_string())),
+      Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+      Err(err) => Err(err.into()),
+    }
+  }
+}
@@ -0,0 +1,387 @@
+use crate::error::{Result, SwagitError};
+use crate::git::{BranchSort, GitManager};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Settings file in the top-level directory of a repository.
+pub const REPO_FILE: &str = ".swagit.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+  Checkout, // open the picker
+  List,     // print the branch table
+  Sync,     // same as --sync
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+  Commit,   // short commit id
+  Upstream, // upstream branch
+  Age,      // age of the last commit
+  Subject,  // subject of the last commit
+  Author,   // author of the last commit
+  Worktree, // worktree the branch is checked out in
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+  Colorful, // colored output and prompts
+  Plain,    // no colors
+}
+
+const MODES: [(&str, Mode); 3] = [
+  ("checkout", Mode::Checkout),
+  ("list", Mode::List),
+  ("sync", Mode::Sync),
+];
+
+const SORTS: [(&str, BranchSort); 3] = [
+  ("committed", BranchSort::Committed),
+  ("checkout", BranchSort::CheckedOut),
+  ("name", BranchSort::Name),
+];
+
+const COLUMNS: [(&str, Column); 6] = [
+  ("commit", Column::Commit),
+  ("upstream", Column::Upstream),
+  ("age", Column::Age),
+  ("subject", Column::Subject),
+  ("author", Column::Author),
+  ("worktree", Column::Worktree),
+];
+
+const THEMES: [(&str, Theme); 2] = [("colorful", Theme::Colorful), ("plain", Theme::Plain)];
+
+/// Every setting with its key in the TOML files and in git config.
+const KEYS: [(&str, &str); 10] = [
+  ("mode", "swagit.mode"),
+  ("sort", "swagit.sort"),
+  ("protected", "swagit.protected"),
+  ("remote", "swagit.remote"),
+  ("default_branch", "swagit.defaultBranch"),
+  ("worktree_base", "swagit.worktreeBase"),
+  ("columns", "swagit.columns"),
+  ("theme", "swagit.theme"),
+  ("confirm", "swagit.confirm"),
+  ("templates", "swagit.template"),
+];
+
+/// Where the value of a setting was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+  Default,
+  File(PathBuf),
+  GitConfig(&'static str),
+}
+
+impl fmt::Display for Source {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Source::Default => write!(f, "default"),
+      Source::File(path) => write!(f, "{}", path.display()),
+      Source::GitConfig(key) => write!(f, "git config {}", key),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Setting<T> {
+  pub value: T,
+  pub source: Source,
+}
+
+impl<T> Setting<T> {
+  fn default(value: T) -> Self {
+    Setting {
+      value,
+      source: Source::Default,
+    }
+  }
+}
+
+/// Settings resolved from, in increasing precedence, the global
+/// `config.toml`, the repository's `.swagit.toml` and `git config swagit.*`.
+#[derive(Debug, Clone)]
+pub struct Config {
+  pub mode: Setting<Mode>,
+  pub sort: Setting<BranchSort>,
+  pub protected: Setting<Vec<String>>,
+  pub remote: Setting<Option<String>>,
+  pub default_branch: Setting<Option<String>>,
+  pub worktree_base: Setting<Option<String>>,
+  pub columns: Setting<Vec<Column>>,
+  pub theme: Setting<Theme>,
+  pub confirm: Setting<bool>,
+  pub templates: Setting<Vec<String>>, // names for new branches like `feature/<ticket>-<slug>`
+  pub files: Vec<PathBuf>,             // settings files that were looked for
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      mode: Setting::default(Mode::Checkout),
+      sort: Setting::default(BranchSort::Committed),
+      protected: Setting::default(vec!["main".to_string(), "master".to_string()]),
+      remote: Setting::default(None),
+      default_branch: Setting::default(None),
+      worktree_base: Setting::default(None),
+      columns: Setting::default(COLUMNS.iter().map(|&(_, column)| column).collect()),
+      theme: Setting::default(Theme::Colorful),
+      confirm: Setting::default(true),
+      templates: Setting::default(Vec::new()),
+      files: Vec::new(),
+    }
+  }
+}
+
+/// A setting as found in one place, before it is parsed.
+struct Entry {
+  key: &'static str,
+  source: Source,
+  values: Vec<String>,
+}
+
+impl Config {
+  pub fn load(git: &GitManager) -> Result<Config> {
+    let mut files = Vec::new();
+    if let Some(path) = global_file() {
+      files.push(path);
+    }
+    if let Ok(toplevel) = git.get_toplevel() {
+      files.push(Path::new(&toplevel).join(REPO_FILE));
+    }
+
+    let mut entries = Vec::new();
+    for path in &files {
+      read_file(path, &mut entries)?;
+    }
+    // One git call for all keys, they are looked up ignoring case like git does
+    let git_entries = git.get_config_regexp(r"^swagit\.");
+    for (key, git_key) in KEYS {
+      let values: Vec<String> = git_entries
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(git_key))
+        .map(|(_, value)| value.clone())
+        .collect();
+      if !values.is_empty() {
+        entries.push(Entry {
+          key,
+          source: Source::GitConfig(git_key),
+          values,
+        });
+      }
+    }
+
+    let defaults = Config::default();
+    Ok(Config {
+      mode: resolve(&entries, "mode", defaults.mode, |values| {
+        one_of(&MODES, last(values))
+      })?,
+      sort: resolve(&entries, "sort", defaults.sort, |values| {
+        one_of(&SORTS, last(values))
+      })?,
+      protected: resolve(&entries, "protected", defaults.protected, |values| {
+        Ok(values.to_vec())
+      })?,
+      remote: resolve(&entries, "remote", defaults.remote, |values| {
+        Ok(Some(last(values).to_string()))
+      })?,
+      default_branch: resolve(
+        &entries,
+        "default_branch",
+        defaults.default_branch,
+        |values| Ok(Some(last(values).to_string())),
+      )?,
+      worktree_base: resolve(
+        &entries,
+        "worktree_base",
+        defaults.worktree_base,
+        |values| Ok(Some(last(values).to_string())),
+      )?,
+      // git config takes a comma separated list, the files an array
+      columns: resolve(&entries, "columns", defaults.columns, |values| {
+        values
+          .iter()
+          .flat_map(|value| value.split(','))
+          .map(str::trim)
+          .filter(|name| !name.is_empty())
+          .map(|name| one_of(&COLUMNS, name))
+          .collect()
+      })?,
+      theme: resolve(&entries, "theme", defaults.theme, |values| {
+        one_of(&THEMES, last(values))
+      })?,
+      confirm: resolve(
+        &entries,
+        "confirm",
+        defaults.confirm,
+        |values| match last(values).to_lowercase().as_str() {
+          "true" | "yes" | "on" | "1" => Ok(true),
+          "false" | "no" | "off" | "0" => Ok(false),
+          other => Err(format!("'{}' is not a boolean", other)),
+        },
+      )?,
+      templates: resolve(&entries, "templates", defaults.templates, |values| {
+        Ok(values.to_vec())
+      })?,
+      files,
+    })
+  }
+
+  /// Name, value and source of every setting, in the order of [`KEYS`].
+  pub fn entries(&self) -> Vec<(&'static str, Value, &Source)> {
+    let optional = |value: &Option<String>| match value {
+      Some(value) => Value::from(value.as_str()),
+      None => Value::Null,
+    };
+    vec![
+      (
+        "mode",
+        name_of(&MODES, self.mode.value).into(),
+        &self.mode.source,
+      ),
+      (
+        "sort",
+        name_of(&SORTS, self.sort.value).into(),
+        &self.sort.source,
+      ),
+      (
+        "protected",
+        self.protected.value.clone().into(),
+        &self.protected.source,
+      ),
+      ("remote", optional(&self.remote.value), &self.remote.source),
+      (
+        "default_branch",
+        optional(&self.default_branch.value),
+        &self.default_branch.source,
+      ),
+      (
+        "worktree_base",
+        optional(&self.worktree_base.value),
+        &self.worktree_base.source,
+      ),
+      (
+        "columns",
+        self
+          .columns
+          .value
+          .iter()
+          .map(|&column| name_of(&COLUMNS, column))
+          .collect::<Vec<_>>()
+          .into(),
+        &self.columns.source,
+      ),
+      (
+        "theme",
+        name_of(&THEMES, self.theme.value).into(),
+        &self.theme.source,
+      ),
+      ("confirm", self.confirm.value.into(), &self.confirm.source),
+      (
+        "templates",
+        self.templates.value.clone().into(),
+        &self.templates.source,
+      ),
+    ]
+  }
+}
+
+/// `$XDG_CONFIG_HOME/swagit/config.toml`, by default in `~/.config`.
+fn global_file() -> Option<PathBuf> {
+  let base = match std::env::var_os("XDG_CONFIG_HOME") {
+    Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+    _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+  };
+  Some(base.join("swagit").join("config.toml"))
+}
+
+/// Adds the settings of a TOML file, a missing file has none.
+fn read_file(path: &Path, entries: &mut Vec<Entry>) -> Result<()> {
+  let text = match std::fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(err.into()),
+  };
+  let invalid =
+    |message: String| SwagitError::InvalidConfig(format!("{}: {}", path.display(), message));
+
+  let table: toml::Table = text
+    .parse()
+    .map_err(|err: toml::de::Error| invalid(err.message().to_string()))?;
+  for (name, value) in table {
+    let key = KEYS
+      .iter()
+      .map(|&(key, _)| key)
+      .find(|&key| key == name)
+      .ok_or_else(|| invalid(format!("unknown setting '{}'", name)))?;
+    let values = match value {
+      toml::Value::String(value) => vec![value],
+      toml::Value::Boolean(value) => vec![value.to_string()],
+      toml::Value::Array(items) => items
+        .into_iter()
+        .map(|item| match item {
+          toml::Value::String(value) => Ok(value),
+          other => Err(invalid(format!(
+            "{} must be a list of strings, not {}",
+            key, other
+          ))),
+        })
+        .collect::<Result<_>>()?,
+      other => return Err(invalid(format!("unsupported value {} for {}", other, key))),
+    };
+    entries.push(Entry {
+      key,
+      source: Source::File(path.to_path_buf()),
+      values,
+    });
+  }
+  Ok(())
+}
+
+/// The setting from the entry with the highest precedence, or the default.
+fn resolve<T>(
+  entries: &[Entry],
+  key: &str,
+  default: Setting<T>,
+  parse: impl Fn(&[String]) -> std::result::Result<T, String>,
+) -> Result<Setting<T>> {
+  match entries.iter().rev().find(|entry| entry.key == key) {
+    Some(entry) => match parse(&entry.values) {
+      Ok(value) => Ok(Setting {
+        value,
+        source: entry.source.clone(),
+      }),
+      Err(message) => Err(SwagitError::InvalidConfig(format!(
+        "{} in {}: {}",
+        key, entry.source, message
+      ))),
+    },
+    None => Ok(default),
+  }
+}
+
+/// The last value wins, like `git config --get`.
+fn last(values: &[String]) -> &str {
+  values.last().map(String::as_str).unwrap_or_default()
+}
+
+fn one_of<T: Copy>(choices: &[(&str, T)], value: &str) -> std::result::Result<T, String> {
+  choices
+    .iter()
+    .find(|(name, _)| *name == value)
+    .map(|&(_, choice)| choice)
+    .ok_or_else(|| {
+      let names: Vec<&str> = choices.iter().map(|&(name, _)| name).collect();
+      format!("'{}' is not one of {}", value, names.join(", "))
+    })
+}
+
+fn name_of<T: PartialEq>(choices: &[(&'static str, T)], value: T) -> &'static str {
+  choices
+    .iter()
+    .find(|(_, choice)| *choice == value)
+    .map(|&(name, _)| name)
+    .unwrap_or_default()
+}
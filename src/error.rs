@@ -0,0 +1,145 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, SwagitError>;
+
+#[derive(Debug)]
+pub enum SwagitError {
+  NotARepository,           // not inside a git repository
+  DetachedHead,             // operation needs a checked out branch
+  NoRemote,                 // no remote configured
+  CheckoutConflict(String), // local changes would be overwritten
+  ProtectedBranch(String),  // branch matches a protected pattern
+  InvalidConfig(String),    // a settings file or swagit.* key is invalid
+  NotInteractive(String),   // an argument is missing and there is no terminal to ask
+  BranchExists(String),     // a new branch name is taken
+  NoMatch(String),          // no branch matches the query
+  NothingToPick(String),    // there are no branches or worktrees to pick from
+  Cancelled,                // user interrupted a prompt
+  UnexpectedOutput(String), // git printed something we could not parse
+  Libgit2(git2::Error),     // in-process backend failure
+  Io(std::io::Error),       // terminal or process I/O failure
+  // rejected by `git check-ref-format --branch`
+  InvalidBranchName(String),
+  // several branches match the query and there is no terminal to pick one
+  AmbiguousMatch {
+    query: String,
+    branches: Vec<String>,
+  },
+  // `git` exited with a failure status
+  GitCommand {
+    code: Option<i32>,
+    stderr: String,
+  },
+}
+
+impl SwagitError {
+  /// Process exit code for this class of error. Usage errors are reported by
+  /// clap with code 2 and Ctrl-C follows the shell convention of 130.
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      SwagitError::GitCommand { .. } | SwagitError::Libgit2(_) | SwagitError::Io(_) => 1,
+      SwagitError::UnexpectedOutput(_) => 1,
+      SwagitError::NotARepository => 3,
+      SwagitError::DetachedHead => 4,
+      SwagitError::NoRemote => 5,
+      SwagitError::CheckoutConflict(_) => 6,
+      // 7 is unused so that the codes after it keep their meaning
+      SwagitError::ProtectedBranch(_) => 8,
+      SwagitError::InvalidConfig(_) => 9,
+      SwagitError::NotInteractive(_) => 2,
+      SwagitError::InvalidBranchName(_) => 10,
+      SwagitError::BranchExists(_) => 11,
+      SwagitError::NoMatch(_) => 12,
+      SwagitError::AmbiguousMatch { .. } => 13,
+      SwagitError::NothingToPick(_) => 14,
+      SwagitError::Cancelled => 130,
+    }
+  }
+
+  /// Classifies a failed `git` invocation by its stderr.
+  pub fn from_git_output(code: Option<i32>, stderr: &str) -> Self {
+    let message = stderr.trim().to_string();
+    if message.contains("not a git repository") {
+      SwagitError::NotARepository
+    } else if message.contains("would be overwritten by checkout")
+      || message.contains("is already checked out at")
+      || message.contains("is already used by worktree at")
+    {
+      SwagitError::CheckoutConflict(message)
+    } else {
+      SwagitError::GitCommand {
+        code,
+        stderr: message,
+      }
+    }
+  }
+}
+
+impl fmt::Display for SwagitError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SwagitError::NotARepository => write!(f, "not a git repository"),
+      SwagitError::DetachedHead => write!(f, "HEAD is detached, check out a branch first"),
+      SwagitError::NoRemote => write!(f, "No remote repository configured"),
+      SwagitError::CheckoutConflict(message) => write!(f, "{}", message),
+      SwagitError::ProtectedBranch(branch) => write!(f, "branch '{}' is protected", branch),
+      SwagitError::InvalidConfig(message) => write!(f, "invalid configuration: {}", message),
+      SwagitError::NotInteractive(message) => write!(f, "{}", message),
+      SwagitError::InvalidBranchName(name) => write!(f, "'{}' is not a valid branch name", name),
+      SwagitError::BranchExists(name) => write!(f, "branch {} already exists", name),
+      SwagitError::NoMatch(query) => write!(f, "no branch matches '{}'", query),
+      SwagitError::NothingToPick(message) => write!(f, "{}", message),
+      SwagitError::AmbiguousMatch { query, branches } => write!(
+        f,
+        "'{}' matches {} branches: {}",
+        query,
+        branches.len(),
+        branches.join(", ")
+      ),
+      SwagitError::Cancelled => write!(f, "cancelled"),
+      SwagitError::UnexpectedOutput(output) => write!(f, "unexpected git output: {}", output),
+      SwagitError::GitCommand { code, stderr } => match code {
+        Some(code) => write!(f, "git exited with code {}: {}", code, stderr),
+        None => write!(f, "git was terminated: {}", stderr),
+      },
+      SwagitError::Libgit2(err) => write!(f, "{}", err.message()),
+      SwagitError::Io(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl std::error::Error for SwagitError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SwagitError::Libgit2(err) => Some(err),
+      SwagitError::Io(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<git2::Error> for SwagitError {
+  fn from(err: git2::Error) -> Self {
+    match (err.class(), err.code()) {
+      (git2::ErrorClass::Repository, git2::ErrorCode::NotFound) => SwagitError::NotARepository,
+      _ => SwagitError::Libgit2(err),
+    }
+  }
+}
+
+impl From<std::io::Error> for SwagitError {
+  fn from(err: std::io::Error) -> Self {
+    match err.kind() {
+      std::io::ErrorKind::Interrupted => SwagitError::Cancelled,
+      _ => SwagitError::Io(err),
+    }
+  }
+}
+
+impl From<dialoguer::Error> for SwagitError {
+  fn from(err: dialoguer::Error) -> Self {
+    match err {
+      dialoguer::Error::IO(err) => err.into(),
+    }
+  }
+}
@@ -1,80 +1,679 @@
-use std::process::Command;
+use crate::backend::{self, GitBackend};
+use crate::config::Config;
+use crate::error::{Result, SwagitError};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BranchInfo {
   pub name: String,
   pub commit_id: String,
   pub worktree_path: Option<String>,
+  pub commit_time: i64,
+  pub author: String,
+  pub subject: String,
+  pub upstream: Option<String>,
+  pub remote: Option<String>, // set for remote-only branches like `origin/feature`
+}
+
+impl BranchInfo {
+  /// Name of the local branch this entry checks out as.
+  pub fn local_name(&self) -> &str {
+    match &self.remote {
+      Some(remote) => self
+        .name
+        .strip_prefix(remote.as_str())
+        .and_then(|name| name.strip_prefix('/'))
+        .unwrap_or(&self.name),
+      None => &self.name,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorktreeInfo {
+  pub path: String,
+  pub branch: Option<String>, // `None` on a detached HEAD
+  pub head: String,
+  pub is_main: bool,
+  pub locked: bool,
+  pub prunable: bool, // its directory is gone
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BranchSort {
+  Name,       // alphabetical
+  Committed,  // most recent commit first
+  CheckedOut, // most recently checked out first
 }
 
 #[derive(Debug)]
 pub enum BranchStatus {
-  Updated(String),    // branch updated
-  Merged(String),     // branch merged can be deleted
-  RemoteGone(String), // remote branch deleted
-  Diverged(String),   // local has unpushed commits
-  UpToDate,           // branch is already up to date
-  LocalOnly(String),  // local branch never pushed
-  Modified(String),   // has uncommitted changes
+  Updated(String),       // branch updated
+  Merged(String),        // branch merged can be deleted
+  RemoteGone(String),    // remote branch deleted
+  Ahead(BranchState),    // local has unpushed commits
+  Diverged(BranchState), // both local and remote have new commits
+  Behind(BranchState),   // behind its push branch, or its upstream in another worktree
+  Pruned(String),        // remote-tracking branch removed by the fetch
+  SquashMerged(String),  // changes landed on the default branch as a squash or rebase
+  UpToDate(String),      // branch is already up to date
+  LocalOnly(String),     // local branch never pushed
+  Modified(String),      // has uncommitted changes
 }
 
-pub struct GitManager;
+/// Serialized flat as `{"status": "ahead", "branch": "feature", ...}`, with
+/// the last commit, tracking counts and suggested action for the variants
+/// that carry a `BranchState`.
+impl Serialize for BranchStatus {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    let (status, branch, state) = match self {
+      BranchStatus::Updated(branch) => ("updated", branch, None),
+      BranchStatus::Merged(branch) => ("merged", branch, None),
+      BranchStatus::RemoteGone(branch) => ("remote_gone", branch, None),
+      BranchStatus::Ahead(state) => ("ahead", &state.name, Some(state)),
+      BranchStatus::Diverged(state) => ("diverged", &state.name, Some(state)),
+      BranchStatus::Behind(state) => ("behind", &state.name, Some(state)),
+      BranchStatus::Pruned(branch) => ("pruned", branch, None),
+      BranchStatus::SquashMerged(branch) => ("squash_merged", branch, None),
+      BranchStatus::UpToDate(branch) => ("up_to_date", branch, None),
+      BranchStatus::LocalOnly(branch) => ("local_only", branch, None),
+      BranchStatus::Modified(branch) => ("modified", branch, None),
+    };
+
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("status", status)?;
+    map.serialize_entry("branch", branch)?;
+    if let Some(state) = state {
+      map.serialize_entry("commit_id", &state.commit_id)?;
+      map.serialize_entry("commit_time", &state.commit_time)?;
+      map.serialize_entry("subject", &state.subject)?;
+      map.serialize_entry("tracking", &state.tracking)?;
+      map.serialize_entry("suggested_action", &state.tracking.suggested_action())?;
+    }
+    map.end()
+  }
+}
+
+/// How a branch compares to the branch it pulls from (`branch.<name>.remote`
+/// and `branch.<name>.merge`) and the one `git push` updates. The two differ
+/// in a triangular workflow, e.g. pulling from `upstream` and pushing to
+/// `origin`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Tracking {
+  pub upstream: Option<String>, // full ref, e.g. `refs/remotes/upstream/main`
+  pub push: Option<String>,     // full ref, `None` when it is the upstream
+  pub upstream_gone: bool,      // configured upstream no longer exists
+  pub upstream_ahead: usize,
+  pub upstream_behind: usize,
+  pub push_ahead: usize,
+  pub push_behind: usize,
+}
+
+impl Tracking {
+  /// What to run to bring the branch in line with its remote, if anything.
+  pub fn suggested_action(&self) -> Option<SuggestedAction> {
+    let short = |refname: &str| refname.trim_start_matches("refs/remotes/").to_string();
+    let upstream = self.upstream.as_deref().filter(|_| !self.upstream_gone);
+
+    match upstream {
+      Some(upstream) if self.upstream_ahead > 0 && self.upstream_behind > 0 => {
+        Some(SuggestedAction::Rebase(short(upstream)))
+      }
+      Some(upstream) if self.upstream_behind > 0 => Some(SuggestedAction::Pull(short(upstream))),
+      None if self.push_ahead == 0 && self.push_behind > 0 => {
+        Some(SuggestedAction::Pull(short(self.push.as_deref()?)))
+      }
+      _ => {
+        let (push, ahead) = match &self.push {
+          Some(push) => (push.as_str(), self.push_ahead),
+          None => (upstream?, self.upstream_ahead),
+        };
+        (ahead > 0).then(|| SuggestedAction::Push(short(push)))
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", content = "remote", rename_all = "snake_case")]
+pub enum SuggestedAction {
+  Push(String),   // local commits are missing on the push branch
+  Rebase(String), // both sides have new commits
+  Pull(String),   // the upstream has new commits
+}
+
+/// A branch with its last commit and how it compares to its remote.
+#[derive(Debug, Clone)]
+pub struct BranchState {
+  pub name: String,
+  pub commit_id: String,
+  pub commit_time: i64,
+  pub subject: String,
+  pub tracking: Tracking,
+}
+
+/// What `sync_branches` may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+  Apply,   // pull, prune and delete merged branches
+  Confirm, // pull and prune, merged branches are left for the caller to delete
+  DryRun,  // change nothing and report what Apply would do
+}
+
+/// A branch removed by swagit, as recorded in the deletion journal.
+#[derive(Debug, Clone)]
+pub struct DeletedBranch {
+  pub name: String,
+  pub commit_id: String,
+  pub deleted_at: i64, // seconds since the epoch
+}
+
+/// How much of a branch's work would be lost by deleting it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", content = "commits", rename_all = "snake_case")]
+pub enum MergeState {
+  Merged,                // reachable from the default branch
+  PatchMerged,           // every commit has an equivalent on the default branch
+  Pushed,                // every commit is on a remote
+  Unpushed(Vec<String>), // commits that exist nowhere else, as "<hash> <subject>"
+}
+
+/// What to do with local changes that would be overwritten by a checkout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocalChanges {
+  Keep,  // no conflict, check out as usual
+  Stash, // stash, switch and re-apply on the target branch
+  Carry, // merge them into the target branch (`git checkout --merge`)
+  Park,  // stash them for the current branch until we switch back
+}
+
+/// What branches are compared against, resolved once per command rather
+/// than once per branch.
+pub struct BranchContext {
+  pub default_branch: String,
+  pub protected: Vec<String>,   // see `get_protected_patterns`
+  default_refs: Vec<String>,    // see `get_default_refs`
+  default_commits: Vec<String>, // commits of `default_refs`, without duplicates
+  merged: Vec<String>,          // local branches reachable from a default ref
+  tracking_refs: HashMap<String, (Option<String>, Option<String>)>, // upstream and push ref
+  default_patch_ids: RefCell<HashMap<(String, String), Vec<String>>>, // by commit and merge base
+}
+
+const PARKED_STASH_PREFIX: &str = "swagit-park:";
+
+pub struct GitManager {
+  backend: Box<dyn GitBackend>,
+  config: Config,
+}
 
 impl GitManager {
-  pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+  pub fn new() -> Result<Self> {
     // check if in git repository
-    match Self.command("rev-parse", &["--git-dir"]) {
-      Ok(_) => Ok(Self),
-      Err(_) => Err("Not a git repository".into()),
-    }
+    let backend = backend::open().map_err(|_| SwagitError::NotARepository)?;
+    let mut git = Self {
+      backend,
+      config: Config::default(),
+    };
+    git.config = Config::load(&git)?;
+    Ok(git)
   }
 
-  pub fn checkout_branch(&self, branch_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    self.command("checkout", &[branch_name])?;
-    Ok(())
+  pub fn config(&self) -> &Config {
+    &self.config
   }
 
-  pub fn delete_branches(&self, branch_names: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-    let mut args = vec!["-D"];
-    args.extend(branch_names.iter().map(|s| s.as_str()));
-    self.command("branch", &args)?;
+  pub fn checkout_branch(&self, branch_name: &str) -> Result<()> {
+    self.backend.checkout(branch_name)
+  }
+
+  pub fn delete_branches(&self, branch_names: &[String]) -> Result<()> {
+    let protected = self.get_protected_patterns()?;
+    let journal = self.get_journal_path()?;
+    for branch in branch_names {
+      self.delete_branch(branch, &protected, &journal)?;
+    }
     Ok(())
   }
 
-  pub fn get_current_branch(&self) -> Result<String, Box<dyn std::error::Error>> {
+  /// Deletes a branch after recording its tip in the deletion journal.
+  fn delete_branch(
+    &self,
+    branch: &str,
+    protected: &[String],
+    journal: &std::path::Path,
+  ) -> Result<()> {
+    if self.is_protected(branch, protected) {
+      return Err(SwagitError::ProtectedBranch(branch.to_string()));
+    }
+
+    let commit_id = self
+      .command("rev-parse", &[&format!("refs/heads/{}", branch)])?
+      .trim()
+      .to_string();
+
+    // Journal first so the tip is never lost, entries of branches that still
+    // exist are skipped when restoring
+    let deleted_at = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|d| d.as_secs() as i64)
+      .unwrap_or(0);
+    if let Some(dir) = journal.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut journal = std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(journal)?;
+    writeln!(journal, "{}\t{}\t{}", deleted_at, commit_id, branch)?;
+
+    self.backend.delete_branch(branch)
+  }
+
+  /// `.git/swagit/deleted-branches`, shared by all worktrees.
+  fn get_journal_path(&self) -> Result<std::path::PathBuf> {
+    let common_dir = self.command("rev-parse", &["--git-common-dir"])?;
     Ok(
-      self
-        .command("branch", &["--show-current"])?
-        .trim()
-        .to_string(),
+      std::path::PathBuf::from(common_dir.trim())
+        .join("swagit")
+        .join("deleted-branches"),
     )
   }
 
-  pub fn get_local_branches(&self) -> Result<Vec<BranchInfo>, Box<dyn std::error::Error>> {
-    let current = self.get_current_branch()?;
+  /// Journaled branches that don't exist anymore, most recently deleted first.
+  pub fn get_deleted_branches(&self) -> Result<Vec<DeletedBranch>> {
+    let journal = match std::fs::read_to_string(self.get_journal_path()?) {
+      Ok(journal) => journal,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err) => return Err(err.into()),
+    };
+
+    let mut deleted: Vec<DeletedBranch> = Vec::new();
+    for line in journal.lines().rev() {
+      let parts: Vec<&str> = line.splitn(3, '\t').collect();
+      if let [deleted_at, commit_id, name] = parts.as_slice() {
+        if deleted.iter().any(|branch| branch.name == *name)
+          || self.backend.ref_exists(&format!("refs/heads/{}", name))
+        {
+          continue;
+        }
+        deleted.push(DeletedBranch {
+          name: name.to_string(),
+          commit_id: commit_id.to_string(),
+          deleted_at: deleted_at.parse().unwrap_or(0),
+        });
+      }
+    }
+
+    Ok(deleted)
+  }
+
+  /// Recreates a deleted branch at the commit it pointed to.
+  pub fn restore_branch(&self, branch: &DeletedBranch) -> Result<()> {
+    self.command("branch", &[&branch.name, &branch.commit_id])?;
+    Ok(())
+  }
+
+  pub fn get_current_branch(&self) -> Result<String> {
+    self.backend.current_branch()
+  }
+
+  /// The remote swagit compares against: the `remote` setting, `origin`, or
+  /// the first configured remote.
+  pub fn get_default_remote(&self) -> Result<Option<String>> {
+    let remotes = self.get_remotes()?;
+    if let Some(remote) = &self.config.remote.value {
+      return Ok(Some(remote.clone()));
+    }
+    if remotes.iter().any(|remote| remote == "origin") {
+      return Ok(Some("origin".to_string()));
+    }
+    Ok(remotes.into_iter().next())
+  }
+
+  /// The branch work gets merged into: the `default_branch` setting,
+  /// `<remote>/HEAD`, or a local `init.defaultBranch`/`main`/`master`,
+  /// falling back to the current branch.
+  pub fn get_default_branch(&self) -> Result<String> {
+    self.default_branch_of(self.get_default_remote()?.as_deref())
+  }
+
+  fn default_branch_of(&self, remote: Option<&str>) -> Result<String> {
+    if let Some(branch) = &self.config.default_branch.value {
+      return Ok(branch.clone());
+    }
+
+    if let Some(remote) = remote {
+      let head = format!("refs/remotes/{}/HEAD", remote);
+      if let Ok(target) = self.command("symbolic-ref", &["--quiet", &head]) {
+        if let Some(branch) = target
+          .trim()
+          .strip_prefix(&format!("refs/remotes/{}/", remote))
+        {
+          return Ok(branch.to_string());
+        }
+      }
+    }
+
+    let candidates = self
+      .get_config("init.defaultBranch")
+      .into_iter()
+      .chain(["main".to_string(), "master".to_string()]);
+    for candidate in candidates {
+      if self
+        .backend
+        .ref_exists(&format!("refs/heads/{}", candidate))
+      {
+        return Ok(candidate);
+      }
+    }
+
+    self.get_current_branch()
+  }
+
+  /// Existing refs of the default branch, the local one first followed by
+  /// the one on the default remote.
+  fn get_default_refs(&self, default_branch: &str, remote: Option<&str>) -> Vec<String> {
+    let mut refs = vec![format!("refs/heads/{}", default_branch)];
+    if let Some(remote) = remote {
+      refs.push(format!("refs/remotes/{}/{}", remote, default_branch));
+    }
+    refs.retain(|refname| self.backend.ref_exists(refname));
+    refs
+  }
+
+  /// Patterns from the `protected` setting (`*` matches any characters)
+  /// and the default branch.
+  pub fn get_protected_patterns(&self) -> Result<Vec<String>> {
+    let mut patterns = self.config.protected.value.clone();
+    patterns.push(self.get_default_branch()?);
+    Ok(patterns)
+  }
+
+  pub fn is_protected(&self, branch: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, branch))
+  }
+
+  /// Default branch, protected patterns, merged branches and tracking refs
+  /// for commands that look at every branch.
+  pub fn branch_context(&self) -> Result<BranchContext> {
+    let remote = self.get_default_remote()?;
+    let default_branch = self.default_branch_of(remote.as_deref())?;
+    let mut protected = self.config.protected.value.clone();
+    protected.push(default_branch.clone());
+    let default_refs = self.get_default_refs(&default_branch, remote.as_deref());
+
+    let mut default_commits: Vec<String> = Vec::new();
+    let mut merged: Vec<String> = Vec::new();
+    if !default_refs.is_empty() {
+      let commits = self.command(
+        "rev-parse",
+        &default_refs.iter().map(String::as_str).collect::<Vec<_>>(),
+      )?;
+      for (default_ref, commit) in default_refs.iter().zip(commits.lines()) {
+        if default_commits.iter().any(|known| known == commit) {
+          continue;
+        }
+        default_commits.push(commit.to_string());
+        for branch in self.backend.merged_branches(default_ref)? {
+          if !merged.contains(&branch) {
+            merged.push(branch);
+          }
+        }
+      }
+    }
+
+    // `%(upstream)` comes from the branch config, so it is kept after the
+    // remote branch was deleted and pruned
     let output = self.command(
       "for-each-ref",
       &[
-        "--format=%(refname:short) %(objectname:short)",
+        "--format=%(refname:lstrip=2)%00%(upstream)%00%(push)",
         "refs/heads/",
       ],
     )?;
+    let tracking_refs = output
+      .lines()
+      .filter_map(|line| {
+        let mut fields = line.split('\0');
+        let branch = fields.next()?.to_string();
+        let upstream = fields
+          .next()
+          .filter(|upstream| !upstream.is_empty())
+          .map(str::to_string);
+        let push = fields
+          .next()
+          .filter(|push| !push.is_empty() && Some(*push) != upstream.as_deref())
+          .map(str::to_string);
+        Some((branch, (upstream, push)))
+      })
+      .collect();
 
+    Ok(BranchContext {
+      default_branch,
+      protected,
+      default_refs,
+      default_commits,
+      merged,
+      tracking_refs,
+      default_patch_ids: Default::default(),
+    })
+  }
+
+  pub fn get_merge_state(&self, branch: &str, context: &BranchContext) -> Result<MergeState> {
+    if context.default_refs.is_empty() {
+      return Err(SwagitError::UnexpectedOutput(format!(
+        "default branch '{}' does not exist",
+        context.default_branch
+      )));
+    }
+    if context.merged.iter().any(|merged| merged == branch) {
+      return Ok(MergeState::Merged);
+    }
+
+    // The local default branch is often behind the remote one during a sync
+    let branch_ref = format!("refs/heads/{}", branch);
+    let mut branch_patch_ids: Vec<(String, Vec<String>, Option<String>)> = Vec::new();
+    for default_commit in &context.default_commits {
+      let merge_base = match self.backend.merge_base(default_commit, &branch_ref)? {
+        Some(merge_base) if merge_base != *default_commit => merge_base,
+        _ => continue, // unrelated histories or nothing new on the default branch
+      };
+      let upstream = self.default_patch_ids(context, default_commit, &merge_base)?;
+
+      let (commits, squashed) = match branch_patch_ids
+        .iter()
+        .find(|(base, ..)| *base == merge_base)
+      {
+        Some((_, commits, squashed)) => (commits.clone(), squashed.clone()),
+        None => {
+          let range = format!("{}..{}", merge_base, branch_ref);
+          let log = self.command(
+            "log",
+            &[
+              "--patch",
+              "--no-color",
+              "--no-ext-diff",
+              "--no-merges",
+              &range,
+            ],
+          )?;
+          let diff = self.command(
+            "diff",
+            &["--no-color", "--no-ext-diff", &merge_base, &branch_ref],
+          )?;
+          // One `git patch-id` for both, the whole diff is marked with a null
+          // commit id
+          let null_id = "0".repeat(merge_base.len());
+          let patches = format!("{}\ncommit {}\n{}", log, null_id, diff);
+          let (squashed, commits): (Vec<_>, Vec<_>) = self
+            .patch_ids(&patches)?
+            .into_iter()
+            .partition(|(_, commit)| *commit == null_id);
+          let commits: Vec<String> = commits.into_iter().map(|(id, _)| id).collect();
+          let squashed = squashed.into_iter().next().map(|(id, _)| id);
+          branch_patch_ids.push((merge_base, commits.clone(), squashed.clone()));
+          (commits, squashed)
+        }
+      };
+
+      // Every commit was cherry-picked or rebased onto the default branch, or
+      // all changes landed there as a single squash commit
+      if !commits.is_empty() && commits.iter().all(|id| upstream.contains(id)) {
+        return Ok(MergeState::PatchMerged);
+      }
+      if squashed.is_some_and(|id| upstream.contains(&id)) {
+        return Ok(MergeState::PatchMerged);
+      }
+    }
+
+    let unpushed = self.command("rev-list", &["--count", &branch_ref, "--not", "--remotes"])?;
+    if unpushed.trim() == "0" {
+      return Ok(MergeState::Pushed);
+    }
+
+    let mut args = vec!["--format=%h %s", &branch_ref, "--not", "--remotes"];
+    args.extend(context.default_refs.iter().map(String::as_str));
+    let unique = self.command("log", &args)?;
+    Ok(MergeState::Unpushed(
+      unique.lines().map(|line| line.to_string()).collect(),
+    ))
+  }
+
+  /// Patch-ids of the commits on `default_commit` since `merge_base`, shared
+  /// by all branches forked from the same commit.
+  fn default_patch_ids(
+    &self,
+    context: &BranchContext,
+    default_commit: &str,
+    merge_base: &str,
+  ) -> Result<Vec<String>> {
+    let key = (default_commit.to_string(), merge_base.to_string());
+    if let Some(patch_ids) = context.default_patch_ids.borrow().get(&key) {
+      return Ok(patch_ids.clone());
+    }
+
+    let range = format!("{}..{}", merge_base, default_commit);
+    let log = self.command(
+      "log",
+      &[
+        "--patch",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-merges",
+        &range,
+      ],
+    )?;
+    let patch_ids: Vec<String> = self
+      .patch_ids(&log)?
+      .into_iter()
+      .map(|(patch_id, _)| patch_id)
+      .collect();
+    context
+      .default_patch_ids
+      .borrow_mut()
+      .insert(key, patch_ids.clone());
+    Ok(patch_ids)
+  }
+
+  /// Stable patch-ids of the patches in `patches` with the commit each one
+  /// belongs to, in order.
+  fn patch_ids(&self, patches: &str) -> Result<Vec<(String, String)>> {
+    if patches.trim().is_empty() {
+      return Ok(Vec::new());
+    }
+    let output = backend::run_git_with_input("patch-id", &["--stable"], patches)?;
+    Ok(
+      output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(patch_id, commit)| (patch_id.to_string(), commit.trim().to_string()))
+        .collect(),
+    )
+  }
+
+  /// Root directory of the current worktree.
+  pub fn get_toplevel(&self) -> Result<String> {
+    Ok(
+      self
+        .command("rev-parse", &["--show-toplevel"])?
+        .trim()
+        .to_string(),
+    )
+  }
+
+  /// Local branches other than the current one.
+  pub fn get_local_branches(&self) -> Result<Vec<BranchInfo>> {
+    let current = self.get_current_branch()?;
+    let mut branches = self.get_all_local_branches()?;
+    branches.retain(|branch| branch.name != current);
+    Ok(branches)
+  }
+
+  /// Local branches including the current one.
+  pub fn get_all_local_branches(&self) -> Result<Vec<BranchInfo>> {
     let worktrees = self.get_worktrees().unwrap_or_default();
 
-    let branches = output
-      .lines()
-      .filter_map(|line| {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() == 2 && parts[0] != current {
-          let worktree_path = worktrees.get(parts[0]).cloned();
-          Some(BranchInfo {
-            name: parts[0].to_string(),
-            commit_id: parts[1].to_string(),
-            worktree_path,
-          })
-        } else {
+    let branches = self
+      .backend
+      .local_branches()?
+      .into_iter()
+      .map(|branch| {
+        let worktree_path = worktrees.get(&branch.name).cloned();
+        BranchInfo {
+          name: branch.name,
+          commit_id: branch.commit_id,
+          worktree_path,
+          commit_time: branch.commit_time,
+          author: branch.author,
+          subject: branch.subject,
+          upstream: branch.upstream,
+          remote: None,
+        }
+      })
+      .collect();
+
+    Ok(branches)
+  }
+
+  /// Remote-tracking branches that have no local branch of the same name.
+  pub fn get_remote_only_branches(&self) -> Result<Vec<BranchInfo>> {
+    let locals: Vec<String> = self
+      .backend
+      .local_branches()?
+      .into_iter()
+      .map(|branch| branch.name)
+      .collect();
+    let remotes = self.get_remotes()?;
+
+    let branches = self
+      .backend
+      .remote_branches()?
+      .into_iter()
+      .filter_map(|branch| {
+        // Remote names may contain slashes, so prefer the longest match
+        let remote = remotes
+          .iter()
+          .filter(|remote| branch.name.starts_with(&format!("{}/", remote)))
+          .max_by_key(|remote| remote.len())?
+          .clone();
+        let info = BranchInfo {
+          name: branch.name,
+          commit_id: branch.commit_id,
+          worktree_path: None,
+          commit_time: branch.commit_time,
+          author: branch.author,
+          subject: branch.subject,
+          upstream: None,
+          remote: Some(remote),
+        };
+        if locals.iter().any(|local| local == info.local_name()) {
           None
+        } else {
+          Some(info)
         }
       })
       .collect();
@@ -82,7 +681,292 @@ impl GitManager {
     Ok(branches)
   }
 
-  pub fn sync_branches(&self) -> Result<Vec<BranchStatus>, Box<dyn std::error::Error>> {
+  pub fn get_remotes(&self) -> Result<Vec<String>> {
+    Ok(
+      self
+        .command("remote", &[])?
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect(),
+    )
+  }
+
+  /// Creates a local branch tracking a remote-only branch.
+  pub fn create_tracking_branch(&self, branch: &BranchInfo) -> Result<()> {
+    self
+      .backend
+      .create_tracking_branch(branch.local_name(), &branch.name)
+  }
+
+  /// Creates a branch at `start` without checking it out.
+  pub fn create_branch(&self, branch: &str, start: &str) -> Result<()> {
+    self.backend.create_branch(branch, start)
+  }
+
+  /// Whether `name` is allowed as a branch name (`git check-ref-format`).
+  pub fn is_valid_branch_name(&self, name: &str) -> bool {
+    !name.starts_with('-')
+      && self
+        .command("check-ref-format", &["--branch", name])
+        .is_ok()
+  }
+
+  pub fn branch_exists(&self, branch: &str) -> bool {
+    self.backend.ref_exists(&format!("refs/heads/{}", branch))
+  }
+
+  /// Names of all remote-tracking branches, like `origin/feature`.
+  pub fn get_remote_branch_names(&self) -> Result<Vec<String>> {
+    Ok(
+      self
+        .backend
+        .remote_branches()?
+        .into_iter()
+        .map(|branch| branch.name)
+        .collect(),
+    )
+  }
+
+  /// Tags, most recently created first.
+  pub fn get_tags(&self) -> Result<Vec<String>> {
+    Ok(
+      self
+        .command(
+          "for-each-ref",
+          &[
+            "--sort=-creatordate",
+            "--format=%(refname:short)",
+            "refs/tags",
+          ],
+        )?
+        .lines()
+        .map(|line| line.to_string())
+        .collect(),
+    )
+  }
+
+  pub fn set_upstream(&self, branch: &str, upstream: &str) -> Result<()> {
+    self.command("branch", &["--set-upstream-to", upstream, branch])?;
+    Ok(())
+  }
+
+  /// Pushes `branch` to `remote` and tracks the pushed branch.
+  pub fn push_with_upstream(&self, remote: &str, branch: &str) -> Result<()> {
+    self.command("push", &["--set-upstream", remote, branch])?;
+    Ok(())
+  }
+
+  /// Renames a local branch. git moves its config, upstream included, along
+  /// and updates the worktree that has it checked out.
+  pub fn rename_branch(&self, branch: &str, new_name: &str) -> Result<()> {
+    self.command("branch", &["--move", branch, new_name])?;
+    Ok(())
+  }
+
+  /// Remote and remote branch name `branch` tracks (`branch.<name>.remote`
+  /// and `branch.<name>.merge`), unless it tracks a local branch.
+  pub fn get_branch_remote(&self, branch: &str) -> Option<(String, String)> {
+    let remote = self.get_config(&format!("branch.{}.remote", branch))?;
+    let merge = self.get_config(&format!("branch.{}.merge", branch))?;
+    match (remote.as_str(), merge.strip_prefix("refs/heads/")) {
+      (".", _) | (_, None) => None,
+      (_, Some(remote_branch)) => Some((remote.clone(), remote_branch.to_string())),
+    }
+  }
+
+  pub fn delete_remote_branch(&self, remote: &str, branch: &str) -> Result<()> {
+    self.command("push", &[remote, "--delete", branch])?;
+    Ok(())
+  }
+
+  /// Paths with local changes that checking out `target` would overwrite.
+  pub fn get_checkout_conflicts(&self, target: &str) -> Result<Vec<String>> {
+    let status = self.command("status", &["--porcelain", "-z", "--untracked-files=all"])?;
+    let mut dirty = Vec::new();
+    let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+      if entry.len() < 4 {
+        continue;
+      }
+      dirty.push(entry[3..].to_string());
+      // Renames and copies are followed by their original path
+      if matches!(entry.as_bytes()[0], b'R' | b'C') {
+        dirty.extend(entries.next().map(|path| path.to_string()));
+      }
+    }
+
+    if dirty.is_empty() {
+      return Ok(dirty);
+    }
+
+    // An unborn HEAD has nothing to compare against. Without rename
+    // detection both paths of a renamed file are listed
+    let changed = self
+      .command(
+        "diff",
+        &["--name-only", "--no-renames", "-z", "HEAD", target],
+      )
+      .unwrap_or_default();
+    let changed: Vec<&str> = changed.split('\0').collect();
+
+    Ok(
+      dirty
+        .into_iter()
+        .filter(|path| changed.contains(&path.as_str()))
+        .collect(),
+    )
+  }
+
+  pub fn checkout_branch_with(&self, branch_name: &str, changes: LocalChanges) -> Result<()> {
+    match changes {
+      LocalChanges::Keep => self.checkout_branch(branch_name),
+      LocalChanges::Carry => {
+        self.command("checkout", &["--merge", branch_name])?;
+        Ok(())
+      }
+      LocalChanges::Stash => {
+        let stashed = self.stash_push("swagit-autostash")?;
+        if let Err(err) = self.checkout_branch(branch_name) {
+          if stashed {
+            self.command("stash", &["pop"])?;
+          }
+          return Err(err);
+        }
+        if stashed {
+          self.command("stash", &["pop"])?;
+        }
+        Ok(())
+      }
+      LocalChanges::Park => {
+        let current = self.get_current_branch()?;
+        let stashed = self.stash_push(&format!("{}{}", PARKED_STASH_PREFIX, current))?;
+        if let Err(err) = self.checkout_branch(branch_name) {
+          if stashed {
+            self.command("stash", &["pop"])?;
+          }
+          return Err(err);
+        }
+        Ok(())
+      }
+    }
+  }
+
+  /// Re-applies changes parked on `branch_name`, returns whether there were any.
+  pub fn restore_parked_changes(&self, branch_name: &str) -> Result<bool> {
+    let marker = format!("{}{}", PARKED_STASH_PREFIX, branch_name);
+    let stashes = self.command("stash", &["list", "--format=%gd %gs"])?;
+    let parked = stashes
+      .lines()
+      .find(|line| line.ends_with(&format!(": {}", marker)))
+      .and_then(|line| line.split(' ').next());
+
+    match parked {
+      Some(stash) => {
+        self.command("stash", &["pop", stash])?;
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+
+  /// Stashes all local changes including untracked files, returns whether
+  /// anything was stashed.
+  fn stash_push(&self, message: &str) -> Result<bool> {
+    let count =
+      |git: &Self| -> Result<usize> { Ok(git.command("stash", &["list"])?.lines().count()) };
+    let before = count(self)?;
+    self.command("stash", &["push", "--include-untracked", "-m", message])?;
+    Ok(count(self)? > before)
+  }
+
+  /// Branch names in the order they were last checked out, most recent first.
+  pub fn get_recent_branches(&self) -> Result<Vec<String>> {
+    let mut recent: Vec<String> = Vec::new();
+    for message in self.backend.head_reflog().unwrap_or_default() {
+      if let Some(target) = message
+        .strip_prefix("checkout: moving from ")
+        .and_then(|rest| rest.split(" to ").nth(1))
+      {
+        if !recent.iter().any(|name| name == target) {
+          recent.push(target.to_string());
+        }
+      }
+    }
+    Ok(recent)
+  }
+
+  /// The branch checked out before the current one, like `git checkout -`.
+  pub fn get_previous_branch(&self) -> Result<Option<String>> {
+    let previous = self
+      .backend
+      .head_reflog()
+      .unwrap_or_default()
+      .iter()
+      .find_map(|message| {
+        message
+          .strip_prefix("checkout: moving from ")
+          .and_then(|rest| rest.split(" to ").next())
+          .map(|name| name.to_string())
+      });
+
+    Ok(previous.filter(|name| self.backend.ref_exists(&format!("refs/heads/{}", name))))
+  }
+
+  /// Reads every `git config` entry whose key matches `pattern` with one
+  /// `git config --get-regexp`, in the order git reports them. Keys are
+  /// lowercased by git except for their subsection.
+  pub fn get_config_regexp(&self, pattern: &str) -> Vec<(String, String)> {
+    self
+      .command("config", &["--null", "--get-regexp", pattern])
+      .map(|entries| {
+        entries
+          .split('\0')
+          .filter(|entry| !entry.is_empty())
+          .map(|entry| match entry.split_once('\n') {
+            Some((key, value)) => (key.to_string(), value.trim().to_string()),
+            None => (entry.to_string(), String::new()),
+          })
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  /// Reads a `git config` value, `None` when unset.
+  pub fn get_config(&self, key: &str) -> Option<String> {
+    self
+      .command("config", &["--get", key])
+      .ok()
+      .map(|value| value.trim().to_string())
+  }
+
+  pub fn sort_branches(&self, branches: &mut [BranchInfo], sort: BranchSort) -> Result<()> {
+    match sort {
+      BranchSort::Name => branches.sort_by(|a, b| a.name.cmp(&b.name)),
+      BranchSort::Committed => branches.sort_by_key(|branch| -branch.commit_time),
+      BranchSort::CheckedOut => {
+        let recent = self.get_recent_branches()?;
+        // Never checked out branches go last, most recent commit first
+        branches.sort_by_key(|branch| {
+          (
+            recent
+              .iter()
+              .position(|name| *name == branch.name)
+              .unwrap_or(usize::MAX),
+            -branch.commit_time,
+          )
+        });
+      }
+    }
+    Ok(())
+  }
+
+  /// Pulls the current branch, prunes remote-tracking branches and deletes
+  /// merged branches. In dry-run mode nothing is changed: the remote is read
+  /// with `git ls-remote` and the statuses describe what a sync would do.
+  pub fn sync_branches(&self, mode: SyncMode) -> Result<Vec<BranchStatus>> {
+    let dry_run = mode == SyncMode::DryRun;
+
     // Check working directory status
     let status = self.command("status", &["--porcelain"])?;
     if !status.is_empty() {
@@ -90,157 +974,462 @@ impl GitManager {
     }
 
     // Check remote
-    let remote_exists = !self.command("remote", &[])?.trim().is_empty();
-    if !remote_exists {
-      return Err("No remote repository configured".into());
+    if self.get_remotes()?.is_empty() {
+      return Err(SwagitError::NoRemote);
     }
 
     let mut statuses = Vec::new();
     let current = self.get_current_branch()?;
-    
+    if current.is_empty() {
+      return Err(SwagitError::DetachedHead);
+    }
+
+    // Where the remote-tracking branches would point after fetching
+    let remote_tips = if dry_run {
+      Some(self.get_remote_tips()?)
+    } else {
+      None
+    };
+    let tips = remote_tips.as_ref();
+
     // Step 1: Sync current branch with remote (similar to hub sync)
-    if let Ok(()) = self.sync_current_branch_with_remote() {
+    if dry_run {
+      let target = self
+        .get_upstream(&current)
+        .and_then(|upstream| self.planned_ref(&upstream, tips));
+      if let Some(target) = target {
+        if let Ok(true) = self
+          .backend
+          .is_ancestor(&format!("refs/heads/{}", current), &target)
+        {
+          statuses.push(BranchStatus::Updated(current.clone()));
+        }
+      }
+    } else if let Ok(()) = self.sync_current_branch_with_remote() {
       statuses.push(BranchStatus::Updated(current.clone()));
     }
 
     // Step 2: Update remote info
-    self.command("remote", &["update", "--prune"])?;
+    let remote_refs = self.get_remote_tracking_refs()?;
+    if !dry_run {
+      self.command("remote", &["update", "--prune"])?;
+    }
+    for refname in remote_refs {
+      if self.planned_ref(&refname, tips).is_none() {
+        let name = refname.trim_start_matches("refs/remotes/");
+        statuses.push(BranchStatus::Pruned(name.to_string()));
+      }
+    }
 
     // Step 3: Delete merged branches (similar to git branch --merged | grep -v master | xargs git branch -d)
-    let deleted_branches = self.delete_merged_branches()?;
-    for branch in deleted_branches {
-      statuses.push(BranchStatus::Merged(branch));
+    let context = self.branch_context()?;
+    let journal = self.get_journal_path()?;
+    let mut merged = Vec::new();
+    for branch in self.get_merged_branches(&current, &context, tips)? {
+      if mode != SyncMode::Apply
+        || self
+          .delete_branch(&branch, &context.protected, &journal)
+          .is_ok()
+      {
+        statuses.push(BranchStatus::Merged(branch.clone()));
+        merged.push(branch);
+      }
     }
 
     // Step 4: Check status of remaining branches
     let remaining_branches = self.get_local_branches()?;
     for branch in remaining_branches {
-      if branch.name != current {
-        statuses.push(self.check_branch_status(&branch.name)?);
+      if branch.name == current || merged.contains(&branch.name) {
+        continue;
+      }
+
+      // Squash and rebase merges aren't reachable, so they survive step 3
+      if !self.is_protected(&branch.name, &context.protected)
+        && matches!(
+          self.get_merge_state(&branch.name, &context),
+          Ok(MergeState::PatchMerged)
+        )
+      {
+        statuses.push(BranchStatus::SquashMerged(branch.name));
+        continue;
+      }
+
+      let tracking = self.get_tracking(&branch.name, &context, tips)?;
+      match self.check_branch_status(&branch, tracking) {
+        // Strictly behind its upstream, so the branch can be moved without a
+        // checkout. Without an upstream it is only reported
+        BranchStatus::Behind(state)
+          if branch.worktree_path.is_none() && state.tracking.upstream.is_some() =>
+        {
+          if let (false, Some(upstream)) = (dry_run, &state.tracking.upstream) {
+            self.backend.fast_forward(&state.name, upstream)?;
+          }
+          statuses.push(BranchStatus::Updated(state.name));
+        }
+        status => statuses.push(status),
       }
     }
 
     Ok(statuses)
   }
 
-  fn sync_current_branch_with_remote(&self) -> Result<(), Box<dyn std::error::Error>> {
+  fn sync_current_branch_with_remote(&self) -> Result<()> {
     let current = self.get_current_branch()?;
-    
-    // Check if current branch has upstream
-    let upstream_result = self.command("rev-parse", &["--abbrev-ref", &format!("{}@{{upstream}}", current)]);
-    
-    if upstream_result.is_ok() {
-      // Pull changes if there's an upstream
+
+    // Pull changes if there's an upstream
+    if self.get_upstream(&current).is_some() {
       self.command("pull", &["--ff-only"])?;
     }
-    
+
     Ok(())
   }
 
-  fn delete_merged_branches(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let current = self.get_current_branch()?;
-    
-    // Get merged branches (excluding master/main and current branch)
-    let merged_output = self.command("branch", &["--merged"])?;
-    let branches_to_delete: Vec<String> = merged_output
-      .lines()
-      .filter_map(|line| {
-        let branch = line.trim().trim_start_matches('*').trim();
-        if !branch.is_empty()
-          && branch != "master"
-          && branch != "main" 
-          && branch != &current
-          && !line.starts_with('*') // Extra safety: don't delete current branch
-        {
-          Some(branch.to_string())
-        } else {
-          None
-        }
-      })
-      .collect();
+  /// Full name of the branch's upstream, e.g. `refs/remotes/origin/main`.
+  fn get_upstream(&self, branch: &str) -> Option<String> {
+    self.symbolic_full_name(&format!("{}@{{upstream}}", branch))
+  }
 
-    // Delete branches
-    let mut deleted = Vec::new();
-    for branch in branches_to_delete {
-      if self.command("branch", &["-d", &branch]).is_ok() {
-        deleted.push(branch);
-      }
-    }
+  /// Local branches whose configured upstream was deleted on the remote.
+  pub fn get_gone_branches(&self) -> Result<Vec<String>> {
+    let output = self.command(
+      "for-each-ref",
+      &[
+        "--format=%(refname:lstrip=2)%00%(upstream:track)",
+        "refs/heads/",
+      ],
+    )?;
+    Ok(
+      output
+        .lines()
+        .filter_map(|line| line.split_once('\0'))
+        .filter(|(_, track)| track.trim() == "[gone]")
+        .map(|(name, _)| name.to_string())
+        .collect(),
+    )
+  }
 
-    Ok(deleted)
+  fn symbolic_full_name(&self, rev: &str) -> Option<String> {
+    self
+      .command("rev-parse", &["--symbolic-full-name", rev])
+      .ok()
+      .map(|name| name.trim().to_string())
+      .filter(|name| !name.is_empty())
+  }
+
+  /// Ahead and behind counts of the branch against its upstream and push
+  /// branch. The push branch follows `branch.<name>.pushRemote`,
+  /// `remote.pushDefault` and `push.default`.
+  pub fn get_branch_tracking(&self, branch: &str, context: &BranchContext) -> Result<Tracking> {
+    self.get_tracking(branch, context, None)
   }
 
-  fn command(&self, cmd: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
-    let output = Command::new("git").arg(cmd).args(args).output()?;
+  /// Like `get_branch_tracking`, as it would be after fetching when
+  /// `remote_tips` is given.
+  fn get_tracking(
+    &self,
+    branch: &str,
+    context: &BranchContext,
+    remote_tips: Option<&std::collections::HashMap<String, String>>,
+  ) -> Result<Tracking> {
+    let local = format!("refs/heads/{}", branch);
+    let mut tracking = Tracking::default();
 
-    if output.status.success() {
-      Ok(String::from_utf8(output.stdout)?)
-    } else {
-      let error = String::from_utf8_lossy(&output.stderr);
-      Err(error.into())
+    let (upstream, push) = context
+      .tracking_refs
+      .get(branch)
+      .cloned()
+      .unwrap_or_default();
+    if let Some(upstream) = &upstream {
+      match self.planned_ref(upstream, remote_tips) {
+        Some(target) => {
+          (tracking.upstream_ahead, tracking.upstream_behind) =
+            self.backend.ahead_behind(&local, &target)?;
+        }
+        None => tracking.upstream_gone = true,
+      }
+      tracking.upstream = Some(upstream.clone());
+    }
+
+    if let Some(target) = push
+      .as_deref()
+      .and_then(|push| self.planned_ref(push, remote_tips))
+    {
+      (tracking.push_ahead, tracking.push_behind) = self.backend.ahead_behind(&local, &target)?;
+      tracking.push = push;
     }
+
+    Ok(tracking)
   }
 
-  fn check_branch_status(&self, branch: &str) -> Result<BranchStatus, Box<dyn std::error::Error>> {
-    // Check if there is an upstream branch
-    let has_upstream = self
-      .command("rev-parse", &["--verify", &format!("refs/remotes/origin/{}", branch)])
-      .is_ok();
+  /// Remote-tracking branches, without the symbolic `<remote>/HEAD`.
+  fn get_remote_tracking_refs(&self) -> Result<Vec<String>> {
+    let output = self.command("for-each-ref", &["--format=%(refname)", "refs/remotes/"])?;
+    Ok(
+      output
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|refname| !refname.is_empty() && !refname.ends_with("/HEAD"))
+        .collect(),
+    )
+  }
 
-    if !has_upstream {
-      return Ok(BranchStatus::LocalOnly(branch.to_string()));
+  /// Branch tips of every remote keyed by the remote-tracking ref they
+  /// would be fetched into, read with `git ls-remote` so nothing is fetched.
+  fn get_remote_tips(&self) -> Result<std::collections::HashMap<String, String>> {
+    let mut tips = std::collections::HashMap::new();
+    for remote in self.get_remotes()? {
+      let output = self.command("ls-remote", &["--heads", &remote])?;
+      for line in output.lines() {
+        if let Some((sha, refname)) = line.split_once('\t') {
+          if let Some(branch) = refname.trim().strip_prefix("refs/heads/") {
+            tips.insert(
+              format!("refs/remotes/{}/{}", remote, branch),
+              sha.to_string(),
+            );
+          }
+        }
+      }
     }
+    Ok(tips)
+  }
 
-    // Use git rev-list to compare local and remote
-    let output = self.command(
-      "rev-list", 
-      &["--left-right", "--count", &format!("{}...origin/{}", branch, branch)]
-    )?;
+  /// The revision `refname` would resolve to after `git remote update
+  /// --prune`, `None` if it would be gone. Without `remote_tips` this is the
+  /// ref as it is now.
+  fn planned_ref(
+    &self,
+    refname: &str,
+    remote_tips: Option<&std::collections::HashMap<String, String>>,
+  ) -> Option<String> {
+    match remote_tips {
+      Some(tips) if refname.starts_with("refs/remotes/") => {
+        let commit = format!("{}^{{commit}}", tips.get(refname)?);
+        if self.backend.ref_exists(&commit) {
+          Some(commit)
+        } else {
+          // Commits that were never fetched can't be compared, the current
+          // remote-tracking ref is the best we know
+          Some(refname.to_string()).filter(|refname| self.backend.ref_exists(refname))
+        }
+      }
+      _ => Some(refname.to_string()).filter(|refname| self.backend.ref_exists(refname)),
+    }
+  }
 
-    let counts: Vec<&str> = output.trim().split_whitespace().collect();
-    match counts.as_slice() {
-      [left, right] => {
-        let local_ahead: usize = left.parse().unwrap_or(0);
-        let local_behind: usize = right.parse().unwrap_or(0);
+  /// Branches merged into the local or remote default branch, excluding
+  /// protected branches and branches checked out here or in another worktree,
+  /// which git refuses to delete.
+  fn get_merged_branches(
+    &self,
+    current: &str,
+    context: &BranchContext,
+    remote_tips: Option<&std::collections::HashMap<String, String>>,
+  ) -> Result<Vec<String>> {
+    let worktrees = self.get_worktrees().unwrap_or_default();
 
-        match (local_ahead, local_behind) {
-          (0, 0) => Ok(BranchStatus::UpToDate),
-          (_, 0) => Ok(BranchStatus::Diverged(branch.to_string())), // Local has unpushed commits
-          (0, _) => Ok(BranchStatus::Updated(branch.to_string())),   // Could be updated (behind remote)
-          (_, _) => Ok(BranchStatus::Diverged(branch.to_string())),  // Both ahead and behind
+    let mut merged: Vec<String> = Vec::new();
+    for default_ref in &context.default_refs {
+      let target = match self.planned_ref(default_ref, remote_tips) {
+        Some(target) => target,
+        None => continue,
+      };
+      for branch in self.backend.merged_branches(&target)? {
+        if !merged.contains(&branch) {
+          merged.push(branch);
         }
       }
-      _ => Ok(BranchStatus::RemoteGone(branch.to_string())),
+    }
+
+    merged.retain(|branch| {
+      !branch.is_empty()
+        && branch != current
+        && !worktrees.contains_key(branch)
+        && !self.is_protected(branch, &context.protected)
+    });
+    Ok(merged)
+  }
+
+  fn command(&self, cmd: &str, args: &[&str]) -> Result<String> {
+    backend::run_git(cmd, args)
+  }
+
+  fn check_branch_status(&self, branch: &BranchInfo, tracking: Tracking) -> BranchStatus {
+    let state = BranchState {
+      name: branch.name.clone(),
+      commit_id: branch.commit_id.clone(),
+      commit_time: branch.commit_time,
+      subject: branch.subject.clone(),
+      tracking,
+    };
+    let tracking = &state.tracking;
+    let branch = branch.name.clone();
+
+    // Pushed once but deleted on the remote since, unlike a branch that was
+    // never pushed and has no tracking configuration
+    if tracking.upstream_gone {
+      return BranchStatus::RemoteGone(branch);
+    }
+
+    // Without an upstream the push branch is all we can compare against
+    if tracking.upstream.is_none() {
+      return match (&tracking.push, tracking.push_ahead) {
+        (None, _) => BranchStatus::LocalOnly(branch),
+        (Some(_), 0) if tracking.push_behind > 0 => BranchStatus::Behind(state),
+        (Some(_), 0) => BranchStatus::UpToDate(branch),
+        (Some(_), _) if tracking.push_behind > 0 => BranchStatus::Diverged(state),
+        (Some(_), _) => BranchStatus::Ahead(state),
+      };
+    }
+
+    // A push branch lagging behind the upstream is normal in a triangular
+    // workflow, so only commits missing from the upstream count as unpushed
+    match (tracking.upstream_ahead, tracking.upstream_behind) {
+      (0, 0) => BranchStatus::UpToDate(branch),
+      (0, _) => BranchStatus::Behind(state), // Can be fast-forwarded
+      (_, 0) => BranchStatus::Ahead(state),
+      (_, _) => BranchStatus::Diverged(state),
     }
   }
 
+  pub fn get_worktrees(&self) -> Result<std::collections::HashMap<String, String>> {
+    Ok(
+      self
+        .get_worktree_list()?
+        .into_iter()
+        .filter_map(|worktree| Some((worktree.branch?, worktree.path)))
+        .collect(),
+    )
+  }
 
-  pub fn get_worktrees(&self) -> Result<std::collections::HashMap<String, String>, Box<dyn std::error::Error>> {
+  pub fn get_worktree_list(&self) -> Result<Vec<WorktreeInfo>> {
     let output = match self.command("worktree", &["list", "--porcelain"]) {
       Ok(output) => output,
-      Err(_) => return Ok(std::collections::HashMap::new()), // No worktrees or git version doesn't support it
+      Err(_) => return Ok(Vec::new()), // No worktrees or git version doesn't support it
     };
 
-    let mut worktrees = std::collections::HashMap::new();
-    let mut current_worktree = String::new();
-    let mut current_branch = String::new();
-
+    // Entries are separated by blank lines, the first one is the main worktree
+    let mut worktrees: Vec<WorktreeInfo> = Vec::new();
     for line in output.lines() {
-      if line.starts_with("worktree ") {
-        current_worktree = line.strip_prefix("worktree ").unwrap_or("").to_string();
-      } else if line.starts_with("branch ") {
-        let branch_ref = line.strip_prefix("branch ").unwrap_or("");
-        if let Some(branch_name) = branch_ref.strip_prefix("refs/heads/") {
-          current_branch = branch_name.to_string();
-        }
+      if let Some(path) = line.strip_prefix("worktree ") {
+        worktrees.push(WorktreeInfo {
+          path: path.to_string(),
+          branch: None,
+          head: String::new(),
+          is_main: worktrees.is_empty(),
+          locked: false,
+          prunable: false,
+        });
+        continue;
       }
 
-      if !current_worktree.is_empty() && !current_branch.is_empty() {
-        worktrees.insert(current_branch.clone(), current_worktree.clone());
-        current_branch.clear();
+      let worktree = match worktrees.last_mut() {
+        Some(worktree) => worktree,
+        None => continue,
+      };
+      if let Some(head) = line.strip_prefix("HEAD ") {
+        worktree.head = head.to_string();
+      } else if let Some(branch_ref) = line.strip_prefix("branch ") {
+        worktree.branch = branch_ref
+          .strip_prefix("refs/heads/")
+          .map(|b| b.to_string());
+      } else if line == "locked" || line.starts_with("locked ") {
+        worktree.locked = true;
+      } else if line == "prunable" || line.starts_with("prunable ") {
+        worktree.prunable = true;
       }
     }
 
     Ok(worktrees)
   }
+
+  pub fn is_worktree_dirty(&self, path: &str) -> Result<bool> {
+    Ok(!backend::run_git_in(path, "status", &["--porcelain"])?.is_empty())
+  }
+
+  /// Directory new worktrees are created in, from the `worktree_base`
+  /// setting or `<repo>.worktrees` next to the main worktree. Relative paths
+  /// are resolved against the main worktree.
+  pub fn get_worktree_base(&self) -> Result<std::path::PathBuf> {
+    let main = self
+      .get_worktree_list()?
+      .into_iter()
+      .find(|worktree| worktree.is_main);
+    let main = match main {
+      Some(worktree) => std::path::PathBuf::from(worktree.path),
+      None => std::path::PathBuf::from(self.get_toplevel()?),
+    };
+
+    Ok(match &self.config.worktree_base.value {
+      Some(base) => main.join(base),
+      None => {
+        let name = main
+          .file_name()
+          .map(|name| name.to_string_lossy().to_string())
+          .unwrap_or_default();
+        main.with_file_name(format!("{}.worktrees", name))
+      }
+    })
+  }
+
+  /// Creates a worktree for `branch` under the worktree base directory.
+  pub fn add_worktree(&self, branch: &str) -> Result<String> {
+    let path = self.get_worktree_base()?.join(branch.replace('/', "-"));
+    let path = path.to_string_lossy().to_string();
+    self.command("worktree", &["add", &path, branch])?;
+    Ok(path)
+  }
+
+  pub fn remove_worktree(&self, worktree: &WorktreeInfo, force: bool) -> Result<()> {
+    let mut args = vec!["remove"];
+    if force {
+      args.push("--force");
+      // Locked worktrees need a second --force
+      if worktree.locked {
+        args.push("--force");
+      }
+    }
+    args.push(&worktree.path);
+    self.command("worktree", &args)?;
+    Ok(())
+  }
+
+  /// Removes administrative entries of worktrees whose directory is gone,
+  /// returns their paths.
+  pub fn prune_worktrees(&self) -> Result<Vec<String>> {
+    let stale: Vec<String> = self
+      .get_worktree_list()?
+      .into_iter()
+      .filter(|worktree| worktree.prunable)
+      .map(|worktree| worktree.path)
+      .collect();
+    self.command("worktree", &["prune"])?;
+    Ok(stale)
+  }
+}
+
+/// Matches `text` against a pattern where `*` matches any run of characters
+/// and `?` a single one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let text: Vec<char> = text.chars().collect();
+  let (mut p, mut t) = (0, 0);
+  let mut backtrack: Option<(usize, usize)> = None;
+
+  while t < text.len() {
+    if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+      p += 1;
+      t += 1;
+    } else if p < pattern.len() && pattern[p] == '*' {
+      backtrack = Some((p, t));
+      p += 1;
+    } else if let Some((star, matched)) = backtrack {
+      p = star + 1;
+      t = matched + 1;
+      backtrack = Some((star, matched + 1));
+    } else {
+      return false;
+    }
+  }
+
+  pattern[p..].iter().all(|&c| c == '*')
 }
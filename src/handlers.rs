@@ -1,85 +1,916 @@
-use crate::git::{BranchStatus, GitManager};
+use crate::config::Column;
+use crate::error::{Result, SwagitError};
+use crate::git::{
+  glob_match, BranchInfo, BranchSort, BranchState, BranchStatus, GitManager, LocalChanges,
+  MergeState, SuggestedAction, SyncMode, Tracking, WorktreeInfo,
+};
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, MultiSelect};
-use std::process;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use dialoguer::{Confirm, FuzzySelect, Input, MultiSelect, Select};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn handle_checkout_command(git: &GitManager) -> Result<(), Box<dyn std::error::Error>> {
-  let branches = git.get_local_branches()?;
+pub struct CheckoutOptions {
+  pub sort: BranchSort,
+  pub all: bool,        // include remote-only branches
+  pub print_path: bool, // print the directory to cd into, messages go to stderr
+  pub json: bool,       // print the branches as JSON instead of picking one
+}
 
-  if branches.is_empty() {
-    eprintln!("{}", "Error: no other branches in the repository".red());
-    process::exit(1);
+pub struct ListOptions {
+  pub sort: BranchSort,
+  pub all: bool,               // include remote-only branches
+  pub pattern: Option<String>, // only branches matching this glob
+  pub merged: Option<bool>,    // only merged (or squash-merged) branches, or only unmerged ones
+  pub gone: bool,              // only branches whose upstream was deleted
+  pub json: bool,
+}
+
+/// A row of `swagit list`, also its `--json` output. Remote-only branches
+/// have no tracking counts nor merge state.
+#[derive(Serialize)]
+struct BranchEntry<'a> {
+  #[serde(flatten)]
+  branch: &'a BranchInfo,
+  current: bool,
+  tracking: Option<Tracking>,
+  merge_state: Option<MergeState>,
+}
+
+/// A worktree in `--json` output.
+#[derive(Serialize)]
+struct WorktreeEntry<'a> {
+  #[serde(flatten)]
+  worktree: &'a WorktreeInfo,
+  dirty: bool,
+}
+
+impl CheckoutOptions {
+  fn report(&self, message: String) {
+    if self.print_path {
+      eprintln!("{}", message);
+    } else {
+      println!("{}", message);
+    }
+  }
+}
+
+pub fn handle_checkout_command(git: &GitManager, options: &CheckoutOptions) -> Result<()> {
+  // Without a terminal to pick from, show the branches rather than guess one
+  if options.json || !is_interactive() {
+    if !options.print_path {
+      handle_list_command(
+        git,
+        &ListOptions {
+          sort: options.sort,
+          all: options.all,
+          pattern: None,
+          merged: None,
+          gone: false,
+          json: options.json,
+        },
+      )?;
+    }
+    if !options.json {
+      eprintln!("Not running in a terminal, no branch was checked out.");
+    }
+    return Ok(());
   }
 
-  let branch_names: Vec<String> = branches
+  let mut branches = git.get_local_branches()?;
+  git.sort_branches(&mut branches, options.sort)?;
+
+  if options.all {
+    let mut remote_branches = git.get_remote_only_branches()?;
+    git.sort_branches(&mut remote_branches, options.sort)?;
+    branches.extend(remote_branches);
+  }
+
+  // The last item creates a branch, so the picker is never empty
+  let mut branch_names = branch_display_names(git, &branches);
+  branch_names.push(format!("{} Create a new branch…", "+".green()));
+
+  let selection = match FuzzySelect::with_theme(&*prompt_theme())
+    .with_prompt("Select the branch to switch to")
+    .items(&branch_names)
+    .default(0)
+    .interact_opt()?
+  {
+    Some(selection) if selection == branches.len() => {
+      return create_branch(git, options, None);
+    }
+    Some(selection) => selection,
+    None => return Ok(()),
+  };
+
+  switch_to(git, &branches[selection], options)
+}
+
+/// Checks out the branch matching `query`, matched like typing it into the
+/// picker: an exact name or a single match is checked out right away,
+/// several matches open the picker filtered by the query.
+pub fn handle_query_command(
+  git: &GitManager,
+  options: &CheckoutOptions,
+  query: &str,
+) -> Result<()> {
+  if git.get_current_branch()? == query {
+    options.report(format!("{} Already on {}", "i".blue(), query));
+    return Ok(());
+  }
+
+  let mut branches = git.get_local_branches()?;
+  git.sort_branches(&mut branches, options.sort)?;
+  if options.all {
+    let mut remote_branches = git.get_remote_only_branches()?;
+    git.sort_branches(&mut remote_branches, options.sort)?;
+    branches.extend(remote_branches);
+  }
+
+  let matcher = SkimMatcherV2::default();
+  let exact = branches
     .iter()
-    .map(|b| {
-      let mut display = format!("{} [{}]", b.name, b.commit_id);
-      if let Some(worktree_path) = &b.worktree_path {
-        if let Some(worktree_name) = std::path::Path::new(worktree_path).file_name() {
-          display.push_str(&format!(" ({})", worktree_name.to_string_lossy()));
+    .position(|b| b.name == query || b.local_name() == query);
+  let matches: Vec<usize> = (0..branches.len())
+    .filter(|&i| matcher.fuzzy_match(&branches[i].name, query).is_some())
+    .collect();
+
+  let selection = match (exact, matches.as_slice()) {
+    (Some(selection), _) | (None, &[selection]) => selection,
+    (None, []) => {
+      if !options.json
+        && is_interactive()
+        && Confirm::with_theme(&*prompt_theme())
+          .with_prompt(format!("No branch matches '{}', create it?", query))
+          .default(true)
+          .interact()?
+      {
+        return create_branch(git, options, Some(query));
+      }
+      return Err(SwagitError::NoMatch(query.to_string()));
+    }
+    (None, _) if options.json || !is_interactive() => {
+      return Err(SwagitError::AmbiguousMatch {
+        query: query.to_string(),
+        branches: matches.iter().map(|&i| branches[i].name.clone()).collect(),
+      });
+    }
+    (None, _) => {
+      match FuzzySelect::with_theme(&*prompt_theme())
+        .with_prompt("Select the branch to switch to")
+        .items(&branch_display_names(git, &branches))
+        .with_initial_text(query)
+        .default(0)
+        .interact_opt()?
+      {
+        Some(selection) => selection,
+        None => return Ok(()),
+      }
+    }
+  };
+
+  switch_to(git, &branches[selection], options)
+}
+
+pub fn handle_list_command(git: &GitManager, options: &ListOptions) -> Result<()> {
+  let current = git.get_current_branch()?;
+  let context = git.branch_context()?;
+
+  let mut branches = git.get_all_local_branches()?;
+  git.sort_branches(&mut branches, options.sort)?;
+  if options.all {
+    let mut remote_branches = git.get_remote_only_branches()?;
+    git.sort_branches(&mut remote_branches, options.sort)?;
+    branches.extend(remote_branches);
+  }
+
+  let mut entries = Vec::new();
+  for branch in &branches {
+    if let Some(pattern) = &options.pattern {
+      if !glob_match(pattern, branch.local_name()) && !glob_match(pattern, &branch.name) {
+        continue;
+      }
+    }
+
+    let (tracking, merge_state) = match branch.remote {
+      Some(_) => (None, None),
+      None => (
+        Some(git.get_branch_tracking(&branch.name, &context)?),
+        git.get_merge_state(&branch.name, &context).ok(),
+      ),
+    };
+
+    let merged = matches!(
+      merge_state,
+      Some(MergeState::Merged) | Some(MergeState::PatchMerged)
+    );
+    if matches!(options.merged, Some(wanted) if wanted != merged) {
+      continue;
+    }
+    if options.gone && !tracking.as_ref().is_some_and(|t| t.upstream_gone) {
+      continue;
+    }
+
+    entries.push(BranchEntry {
+      branch,
+      current: branch.remote.is_none() && branch.name == current,
+      tracking,
+      merge_state,
+    });
+  }
+
+  if options.json {
+    return print_json(&serde_json::json!({
+      "current": current,
+      "branches": entries,
+    }));
+  }
+
+  if entries.is_empty() {
+    println!("No branches match.");
+    return Ok(());
+  }
+
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+
+  // Columns: branch, commit, age, upstream, ahead/behind, worktree, state
+  let mut rows = vec![[
+    "BRANCH", "COMMIT", "AGE", "UPSTREAM", "SYNC", "WORKTREE", "STATE",
+  ]
+  .map(String::from)];
+  for entry in &entries {
+    let branch = entry.branch;
+    let name = match branch.remote {
+      Some(_) => format!("remotes/{}", branch.name),
+      None => branch.name.clone(),
+    };
+
+    let (upstream, counts) = match &entry.tracking {
+      Some(Tracking {
+        upstream: Some(upstream),
+        upstream_gone,
+        upstream_ahead,
+        upstream_behind,
+        ..
+      }) => {
+        let upstream = upstream.trim_start_matches("refs/remotes/");
+        if *upstream_gone {
+          (format!("{} (gone)", upstream), String::new())
+        } else {
+          (
+            upstream.to_string(),
+            format!("↑{} ↓{}", upstream_ahead, upstream_behind),
+          )
         }
       }
-      display
-    })
-    .collect();
+      _ => ("-".to_string(), String::new()),
+    };
+
+    // The current branch is in this worktree
+    let worktree = branch
+      .worktree_path
+      .as_deref()
+      .filter(|_| !entry.current)
+      .and_then(|path| std::path::Path::new(path).file_name())
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+
+    let state = match &entry.merge_state {
+      Some(MergeState::Merged) if branch.name == context.default_branch => "default".to_string(),
+      Some(MergeState::Merged) => "merged".to_string(),
+      Some(MergeState::PatchMerged) => "squash-merged".to_string(),
+      Some(MergeState::Pushed) => "pushed".to_string(),
+      Some(MergeState::Unpushed(commits)) => format!("{} unpushed", commits.len()),
+      None => String::new(),
+    };
+
+    rows.push([
+      name,
+      branch.commit_id.clone(),
+      relative_time(now - branch.commit_time),
+      upstream,
+      counts,
+      worktree,
+      state,
+    ]);
+  }
+
+  let mut widths = [0; 7];
+  for row in &rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.chars().count());
+    }
+  }
+
+  for (i, row) in rows.iter().enumerate() {
+    let marker = match i.checked_sub(1).map(|i| &entries[i]) {
+      None => " ",
+      Some(entry) if entry.current => "*",
+      Some(entry) if entry.branch.worktree_path.is_some() => "+",
+      Some(_) => " ",
+    };
+    let line = row
+      .iter()
+      .zip(widths)
+      .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+      .collect::<Vec<String>>()
+      .join("  ");
+    let line = line.trim_end();
+
+    match (i, marker) {
+      (0, _) => println!("  {}", line.dimmed()),
+      (_, "*") => println!("{} {}", marker.green(), line.green()),
+      (_, "+") => println!("{} {}", marker.cyan(), line),
+      _ => println!("{} {}", marker, line),
+    }
+  }
+
+  Ok(())
+}
+
+/// Prompts are drawn on stderr, so stdout may be captured by a shell wrapper.
+fn is_interactive() -> bool {
+  atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stderr)
+}
+
+fn switch_to(git: &GitManager, branch: &BranchInfo, options: &CheckoutOptions) -> Result<()> {
+  // A branch checked out in another worktree can't be checked out here
+  if let Some(worktree_path) = &branch.worktree_path {
+    if options.print_path {
+      println!("{}", worktree_path);
+    } else {
+      println!(
+        "{} Branch {} is checked out in worktree {}",
+        "i".blue(),
+        branch.name,
+        worktree_path
+      );
+      println!("  cd {}", worktree_path);
+    }
+    return Ok(());
+  }
+
+  let changes = match ask_local_changes(git, branch, options)? {
+    Some(changes) => changes,
+    None => {
+      options.report("Checkout aborted.".to_string());
+      return Ok(());
+    }
+  };
+
+  if branch.remote.is_some() {
+    git.create_tracking_branch(branch)?;
+    git.checkout_branch_with(branch.local_name(), changes)?;
+    options.report(
+      format!(
+        "Switched to a new branch {} tracking {}",
+        branch.local_name(),
+        branch.name
+      )
+      .green()
+      .to_string(),
+    );
+  } else {
+    git.checkout_branch_with(&branch.name, changes)?;
+    options.report(
+      format!("Switched to branch {}", branch.name)
+        .green()
+        .to_string(),
+    );
+  }
+
+  match git.restore_parked_changes(branch.local_name()) {
+    Ok(true) => options.report(format!(
+      "{} Restored changes parked on {}",
+      "✓".green(),
+      branch.local_name()
+    )),
+    Ok(false) => (),
+    Err(err) => options.report(format!(
+      "{} Could not restore changes parked on {}, they are still stashed: {}",
+      "!".yellow(),
+      branch.local_name(),
+      err
+    )),
+  }
+
+  if options.print_path {
+    println!("{}", git.get_toplevel()?);
+  }
+
+  Ok(())
+}
+
+/// Asks what to do with local changes that would be overwritten by checking
+/// out `branch`. `None` means the checkout was aborted.
+fn ask_local_changes(
+  git: &GitManager,
+  branch: &BranchInfo,
+  options: &CheckoutOptions,
+) -> Result<Option<LocalChanges>> {
+  let conflicts = git.get_checkout_conflicts(&branch.name)?;
+  if conflicts.is_empty() {
+    return Ok(Some(LocalChanges::Keep));
+  }
+
+  if !is_interactive() {
+    return Err(SwagitError::CheckoutConflict(format!(
+      "Your local changes to the following files would be overwritten by checkout:\n  {}",
+      conflicts.join("\n  ")
+    )));
+  }
+
+  options.report(format!(
+    "{} Local changes would be overwritten by checking out {}:",
+    "!".yellow(),
+    branch.local_name()
+  ));
+  for path in &conflicts {
+    options.report(format!("  {}", path));
+  }
+
+  let current = git.get_current_branch()?;
+  let choices = [
+    format!("Stash changes and re-apply them on {}", branch.local_name()),
+    format!("Carry changes over to {}", branch.local_name()),
+    format!("Park changes on {} until switching back", current),
+    "Abort".to_string(),
+  ];
+  let selection = Select::with_theme(&*prompt_theme())
+    .with_prompt("What should happen to your local changes?")
+    .items(&choices)
+    .default(0)
+    .interact_opt()?;
 
-  if atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stdout) {
-    let selection = match FuzzySelect::with_theme(&ColorfulTheme::default())
-      .with_prompt("Select the branch to switch to")
-      .items(&branch_names)
+  Ok(match selection {
+    Some(0) => Some(LocalChanges::Stash),
+    Some(1) => Some(LocalChanges::Carry),
+    Some(2) => Some(LocalChanges::Park),
+    _ => None,
+  })
+}
+
+pub fn handle_previous_command(git: &GitManager, options: &CheckoutOptions) -> Result<()> {
+  let branch_name = match git.get_previous_branch()? {
+    Some(branch_name) => branch_name,
+    None => {
+      return Err(SwagitError::NothingToPick(
+        "no previous branch to switch to".to_string(),
+      ));
+    }
+  };
+
+  let branch = git
+    .get_local_branches()?
+    .into_iter()
+    .find(|branch| branch.name == branch_name);
+  match branch {
+    Some(branch) => switch_to(git, &branch, options),
+    None => Ok(()),
+  }
+}
+
+pub fn handle_new_command(
+  git: &GitManager,
+  options: &CheckoutOptions,
+  name: Option<&str>,
+) -> Result<()> {
+  if name.is_none() && !is_interactive() {
+    return Err(SwagitError::NotInteractive(
+      "a branch name is needed outside a terminal".to_string(),
+    ));
+  }
+  create_branch(git, options, name)
+}
+
+/// Where a new branch should track, asked after picking its base.
+enum NewUpstream {
+  Track(String), // the remote branch it was created from
+  Push(String),  // push it to this remote under the same name
+}
+
+/// Creates a branch named after `typed` or a naming template, from a base
+/// picked by the user, and checks it out. Outside a terminal the branch is
+/// created from HEAD as named, without an upstream.
+fn create_branch(git: &GitManager, options: &CheckoutOptions, typed: Option<&str>) -> Result<()> {
+  let name = match new_branch_name(git, typed)? {
+    Some(name) => name,
+    None => return Ok(()),
+  };
+  if !git.is_valid_branch_name(&name) {
+    return Err(SwagitError::InvalidBranchName(name));
+  }
+  if git.branch_exists(&name) {
+    return Err(SwagitError::BranchExists(name));
+  }
+
+  let (base, upstream) = if is_interactive() {
+    let base = match pick_base(git)? {
+      Some(base) => base,
+      None => return Ok(()),
+    };
+    let upstream = match pick_upstream(git, &name, &base)? {
+      Some(upstream) => upstream,
+      None => return Ok(()),
+    };
+    (base, upstream)
+  } else {
+    ("HEAD".to_string(), None)
+  };
+
+  git.create_branch(&name, &base)?;
+  options.report(format!(
+    "{} Created branch {} from {}",
+    "✓".green(),
+    name,
+    base
+  ));
+  match upstream {
+    Some(NewUpstream::Track(remote_branch)) => {
+      git.set_upstream(&name, &remote_branch)?;
+      options.report(format!("{} Tracking {}", "✓".green(), remote_branch));
+    }
+    Some(NewUpstream::Push(remote)) => {
+      git.push_with_upstream(&remote, &name)?;
+      options.report(format!("{} Pushed to {}/{}", "✓".green(), remote, name));
+    }
+    None => (),
+  }
+
+  let branch = git
+    .get_local_branches()?
+    .into_iter()
+    .find(|branch| branch.name == name);
+  match branch {
+    Some(branch) => switch_to(git, &branch, options),
+    None => Ok(()),
+  }
+}
+
+/// Asks for the name of a new branch, filling in one of the configured
+/// naming templates when there are any. `typed` is what the user already
+/// entered; it becomes the name or the template's `<slug>`.
+fn new_branch_name(git: &GitManager, typed: Option<&str>) -> Result<Option<String>> {
+  let templates = &git.config().templates.value;
+  if !is_interactive() || (templates.is_empty() && typed.is_some()) {
+    return Ok(typed.map(|name| name.to_string()));
+  }
+
+  let mut items = templates.clone();
+  items.push(match typed {
+    Some(typed) => format!("{} (as typed)", typed),
+    None => "No template".to_string(),
+  });
+  let template = if templates.is_empty() {
+    None
+  } else {
+    match Select::with_theme(&*prompt_theme())
+      .with_prompt("Name the branch after")
+      .items(&items)
       .default(0)
       .interact_opt()?
     {
-      Some(selections) => selections,
-      None => return Ok(()),
+      Some(selection) if selection < templates.len() => Some(&templates[selection]),
+      Some(_) => None,
+      None => return Ok(None),
+    }
+  };
+
+  let name = match template {
+    Some(template) => {
+      let theme = prompt_theme();
+      let mut name = template.clone();
+      for placeholder in template_placeholders(template) {
+        let mut input = Input::<String>::with_theme(&*theme).with_prompt(placeholder);
+        if let (Some(typed), "slug") = (typed, placeholder) {
+          input = input.with_initial_text(typed);
+        }
+        let value = input.interact_text()?;
+        let value = match placeholder {
+          "slug" => slugify(&value),
+          _ => value.trim().replace(char::is_whitespace, "-"),
+        };
+        name = name.replace(&format!("<{}>", placeholder), &value);
+      }
+      name
+    }
+    None => match typed {
+      Some(typed) => typed.to_string(),
+      None => Input::<String>::with_theme(&*prompt_theme())
+        .with_prompt("New branch name")
+        .validate_with(|name: &String| match git.is_valid_branch_name(name) {
+          true => Ok(()),
+          false => Err("not a valid branch name"),
+        })
+        .interact_text()?,
+    },
+  };
+  Ok(Some(name))
+}
+
+/// Placeholders like `<ticket>` in a naming template, each once.
+fn template_placeholders(template: &str) -> Vec<&str> {
+  let mut placeholders = Vec::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('<') {
+    let Some(end) = rest[start..].find('>') else {
+      break;
     };
+    let placeholder = &rest[start + 1..start + end];
+    if !placeholder.is_empty() && !placeholders.contains(&placeholder) {
+      placeholders.push(placeholder);
+    }
+    rest = &rest[start + end + 1..];
+  }
+  placeholders
+}
 
-    let branch_name = &branches[selection].name;
-    git.checkout_branch(branch_name)?;
-    println!("{}", format!("Switched to branch {}", branch_name).green());
-  } else {
-    let branch_name = &branches[0].name;
-    git.checkout_branch(branch_name)?;
-    println!("{}", format!("Switched to branch {}", branch_name).green());
+/// "Fix the Login form!" becomes "fix-the-login-form".
+fn slugify(text: &str) -> String {
+  text
+    .to_lowercase()
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>()
+    .join("-")
+}
+
+/// Asks what to create a new branch from: HEAD, the default branch, a
+/// remote branch or a tag.
+fn pick_base(git: &GitManager) -> Result<Option<String>> {
+  let current = git.get_current_branch()?;
+  let default_branch = git.get_default_branch()?;
+  let remote_branches = git.get_remote_branch_names()?;
+  let tags = git.get_tags()?;
+
+  let mut items = vec![match current.as_str() {
+    "" => "Current HEAD".to_string(),
+    current => format!("Current HEAD ({})", current),
+  }];
+  items.push(format!("Default branch ({})", default_branch));
+  if !remote_branches.is_empty() {
+    items.push("A remote branch".to_string());
+  }
+  if !tags.is_empty() {
+    items.push("A tag".to_string());
   }
 
+  let selection = match Select::with_theme(&*prompt_theme())
+    .with_prompt("Create the branch from")
+    .items(&items)
+    .default(0)
+    .interact_opt()?
+  {
+    Some(selection) => selection,
+    None => return Ok(None),
+  };
+
+  let refs = match items[selection].as_str() {
+    "A remote branch" => &remote_branches,
+    "A tag" => &tags,
+    _ if selection == 0 => return Ok(Some("HEAD".to_string())),
+    // The default branch may only exist on the remote
+    _ => {
+      return Ok(Some(match git.get_default_remote()? {
+        Some(remote) if !git.branch_exists(&default_branch) => {
+          format!("{}/{}", remote, default_branch)
+        }
+        _ => default_branch,
+      }))
+    }
+  };
+  Ok(
+    FuzzySelect::with_theme(&*prompt_theme())
+      .with_prompt("Select the base")
+      .items(refs)
+      .default(0)
+      .interact_opt()?
+      .map(|selection| refs[selection].clone()),
+  )
+}
+
+/// Asks whether a new branch should track the remote branch it was created
+/// from or be pushed to the default remote. `None` when the user dismissed
+/// the question, `Some(None)` for no upstream.
+fn pick_upstream(git: &GitManager, name: &str, base: &str) -> Result<Option<Option<NewUpstream>>> {
+  let mut choices = vec![(None, "No upstream".to_string())];
+  if git
+    .get_remote_branch_names()?
+    .iter()
+    .any(|branch| branch == base)
+  {
+    choices.push((
+      Some(NewUpstream::Track(base.to_string())),
+      format!("Track {}", base),
+    ));
+  }
+  if let Some(remote) = git.get_default_remote()? {
+    let label = format!("Push to {} and track {}/{}", remote, remote, name);
+    choices.push((Some(NewUpstream::Push(remote)), label));
+  }
+  if choices.len() == 1 {
+    return Ok(Some(None));
+  }
+
+  let items: Vec<&String> = choices.iter().map(|(_, label)| label).collect();
+  let selection = match Select::with_theme(&*prompt_theme())
+    .with_prompt("Upstream")
+    .items(&items)
+    .default(0)
+    .interact_opt()?
+  {
+    Some(selection) => selection,
+    None => return Ok(None),
+  };
+  Ok(Some(choices.swap_remove(selection).0))
+}
+
+/// Renames a local branch, picked or given as `branch`, and offers to move
+/// its remote branch along. With only `new_name` the current branch is
+/// renamed, like `git branch -m`.
+pub fn handle_rename_command(
+  git: &GitManager,
+  branch: Option<&str>,
+  new_name: Option<&str>,
+) -> Result<()> {
+  let mut branches = git.get_all_local_branches()?;
+  git.sort_branches(&mut branches, BranchSort::Committed)?;
+  let current = git.get_current_branch()?;
+
+  let branch = match (branch, new_name) {
+    (Some(branch), _) => branch.to_string(),
+    (None, Some(_)) if !current.is_empty() => current.clone(),
+    (None, Some(_)) => return Err(SwagitError::DetachedHead),
+    (None, None) if !is_interactive() => {
+      return Err(SwagitError::NotInteractive(
+        "a branch and its new name are needed outside a terminal".to_string(),
+      ));
+    }
+    (None, None) => {
+      let default = branches.iter().position(|b| b.name == current).unwrap_or(0);
+      match FuzzySelect::with_theme(&*prompt_theme())
+        .with_prompt("Select the branch to rename")
+        .items(&branch_display_names(git, &branches))
+        .default(default)
+        .interact_opt()?
+      {
+        Some(selection) => branches[selection].name.clone(),
+        None => return Ok(()),
+      }
+    }
+  };
+  let Some(info) = branches.iter().find(|b| b.name == branch) else {
+    return Err(SwagitError::NoMatch(branch));
+  };
+
+  let new_name = match new_name {
+    Some(new_name) => new_name.to_string(),
+    None if !is_interactive() => {
+      return Err(SwagitError::NotInteractive(
+        "a new name is needed outside a terminal".to_string(),
+      ));
+    }
+    None => Input::<String>::with_theme(&*prompt_theme())
+      .with_prompt(format!("New name for {}", branch))
+      .with_initial_text(&branch)
+      .validate_with(|name: &String| {
+        if !git.is_valid_branch_name(name) {
+          Err("not a valid branch name")
+        } else if *name != branch && git.branch_exists(name) {
+          Err("a branch with this name already exists")
+        } else {
+          Ok(())
+        }
+      })
+      .interact_text()?,
+  };
+  if new_name == branch {
+    println!("{}", "Nothing to rename".green());
+    return Ok(());
+  }
+  if !git.is_valid_branch_name(&new_name) {
+    return Err(SwagitError::InvalidBranchName(new_name));
+  }
+  if git.branch_exists(&new_name) {
+    return Err(SwagitError::BranchExists(new_name));
+  }
+
+  git.rename_branch(&branch, &new_name)?;
+  println!("{} Renamed branch {} to {}", "✓".green(), branch, new_name);
+  if let Some(worktree_path) = &info.worktree_path {
+    if *worktree_path != git.get_toplevel()? {
+      println!("{} Updated worktree {}", "✓".green(), worktree_path);
+    }
+  }
+
+  // The upstream still points at the old name on the remote
+  let Some((remote, remote_branch)) = git.get_branch_remote(&new_name) else {
+    return Ok(());
+  };
+  if !is_interactive() {
+    println!(
+      "{} {} still tracks {}/{}",
+      "i".blue(),
+      new_name,
+      remote,
+      remote_branch
+    );
+    return Ok(());
+  }
+  if !Confirm::with_theme(&*prompt_theme())
+    .with_prompt(format!(
+      "Push {} to {} and track {}/{}?",
+      new_name, remote, remote, new_name
+    ))
+    .default(true)
+    .interact()?
+  {
+    return Ok(());
+  }
+  git.push_with_upstream(&remote, &new_name)?;
+  println!("{} Pushed {} to {}", "✓".green(), new_name, remote);
+
+  // Only the remote branch of the same name was this branch's own
+  if remote_branch == branch
+    && Confirm::with_theme(&*prompt_theme())
+      .with_prompt(format!("Delete {}/{} on the remote?", remote, branch))
+      .default(false)
+      .interact()?
+  {
+    git.delete_remote_branch(&remote, &branch)?;
+    println!("{} Deleted {}/{}", "✓".green(), remote, branch);
+  }
+
+  Ok(())
+}
+
+pub fn handle_delete_command(git: &GitManager, json: bool) -> Result<()> {
+  let deleted = select_and_delete_branches(git, json)?;
+  if json {
+    print_json(&serde_json::json!({ "deleted": deleted }))?;
+  }
   Ok(())
 }
 
-pub fn handle_delete_command(git: &GitManager) -> Result<(), Box<dyn std::error::Error>> {
-  let branches = git.get_local_branches()?;
+/// Returns the branches the user picked and confirmed, after deleting them.
+fn select_and_delete_branches(git: &GitManager, json: bool) -> Result<Vec<String>> {
+  let context = git.branch_context()?;
+  let branches: Vec<BranchInfo> = git
+    .get_local_branches()?
+    .into_iter()
+    .filter(|branch| !git.is_protected(&branch.name, &context.protected))
+    .collect();
 
   if branches.is_empty() {
-    eprintln!("{}", "Error: no other branches in the repository".red());
-    process::exit(1);
+    return Err(SwagitError::NothingToPick(
+      "no other branches in the repository".to_string(),
+    ));
   }
 
-  let branch_names: Vec<String> = branches
+  let default_branch = &context.default_branch;
+  let states = branches
     .iter()
-    .map(|b| {
-      let mut display = format!("{} [{}]", b.name, b.commit_id);
-      if let Some(worktree_path) = &b.worktree_path {
-        if let Some(worktree_name) = std::path::Path::new(worktree_path).file_name() {
-          display.push_str(&format!(" ({})", worktree_name.to_string_lossy()));
-        }
+    .map(|b| git.get_merge_state(&b.name, &context))
+    .collect::<Result<Vec<MergeState>>>()?;
+
+  let gone = git.get_gone_branches()?;
+  let branch_names: Vec<String> = branch_display_names(git, &branches)
+    .into_iter()
+    .zip(&branches)
+    .zip(&states)
+    .map(|((display, branch), state)| {
+      let label = match state {
+        MergeState::Merged => format!("merged into {}", default_branch),
+        MergeState::PatchMerged => format!("squash/rebase merged into {}", default_branch),
+        MergeState::Pushed => "pushed".to_string(),
+        MergeState::Unpushed(commits) if commits.len() == 1 => "1 unpushed commit".to_string(),
+        MergeState::Unpushed(commits) => format!("{} unpushed commits", commits.len()),
+      };
+      if gone.contains(&branch.name) {
+        format!("{} <{}, upstream gone>", display, label)
+      } else {
+        format!("{} <{}>", display, label)
       }
-      display
     })
     .collect();
 
-  let selections = match MultiSelect::with_theme(&ColorfulTheme::default())
+  let selections = match MultiSelect::with_theme(&*prompt_theme())
     .with_prompt("Select the branches to delete")
     .items(&branch_names)
     .interact_opt()?
   {
     Some(selections) => selections,
-    None => return Ok(()),
+    None => return Ok(Vec::new()),
   };
 
   if selections.is_empty() {
-    println!("No branches selected, exiting.");
-    return Ok(());
+    report(json, "No branches selected, exiting.".to_string());
+    return Ok(Vec::new());
   }
 
   let selected_branches: Vec<String> = selections
@@ -100,39 +931,189 @@ pub fn handle_delete_command(git: &GitManager) -> Result<(), Box<dyn std::error:
     )
   };
 
-  if Confirm::with_theme(&ColorfulTheme::default())
-    .with_prompt(message)
-    .interact()?
+  if git.config().confirm.value
+    && !Confirm::with_theme(&*prompt_theme())
+      .with_prompt(message)
+      .interact()?
   {
-    git.delete_branches(&selected_branches)?;
-    println!(
-      "{}",
-      format!("Deleted {} branches", selected_branches.len()).green()
+    return Ok(Vec::new());
+  }
+
+  // Branches with work that exists nowhere else need a second confirmation
+  let unmerged: Vec<(&String, &Vec<String>)> = selections
+    .iter()
+    .filter_map(|&i| match &states[i] {
+      MergeState::Unpushed(commits) => Some((&branches[i].name, commits)),
+      _ => None,
+    })
+    .collect();
+
+  let mut to_delete = selected_branches.clone();
+  if !unmerged.is_empty() {
+    report(
+      json,
+      format!(
+        "{} These branches have commits that are not merged or pushed anywhere:",
+        "!".red()
+      ),
     );
+    for (branch, commits) in &unmerged {
+      report(json, format!("  {}", branch.yellow()));
+      for commit in *commits {
+        report(json, format!("    {}", commit));
+      }
+    }
+
+    let force = Confirm::with_theme(&*prompt_theme())
+      .with_prompt("Delete them anyway? Their commits will be lost")
+      .default(false)
+      .interact()?;
+    if !force {
+      to_delete.retain(|branch| !unmerged.iter().any(|(name, _)| *name == branch));
+    }
+  }
+
+  if to_delete.is_empty() {
+    report(json, "No branches deleted.".to_string());
+    return Ok(Vec::new());
+  }
+
+  git.delete_branches(&to_delete)?;
+  report(
+    json,
+    format!("Deleted {} branches", to_delete.len())
+      .green()
+      .to_string(),
+  );
+
+  Ok(to_delete)
+}
+
+pub fn handle_restore_command(git: &GitManager) -> Result<()> {
+  let deleted = git.get_deleted_branches()?;
+
+  if deleted.is_empty() {
+    return Err(SwagitError::NothingToPick(
+      "no deleted branches to restore".to_string(),
+    ));
+  }
+
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+  let name_width = deleted.iter().map(|b| b.name.len()).max().unwrap_or(0);
+  let items: Vec<String> = deleted
+    .iter()
+    .map(|b| {
+      let commit_id: String = b.commit_id.chars().take(7).collect();
+      format!(
+        "{:<name_width$} [{}] deleted {}",
+        b.name,
+        commit_id,
+        relative_time(now - b.deleted_at)
+      )
+    })
+    .collect();
+
+  let selections = match MultiSelect::with_theme(&*prompt_theme())
+    .with_prompt("Select the branches to restore")
+    .items(&items)
+    .interact_opt()?
+  {
+    Some(selections) => selections,
+    None => return Ok(()),
+  };
+
+  if selections.is_empty() {
+    println!("No branches selected, exiting.");
+    return Ok(());
+  }
+
+  for i in selections {
+    let branch = &deleted[i];
+    match git.restore_branch(branch) {
+      Ok(()) => println!("{} Restored branch {}", "✓".green(), branch.name.green()),
+      Err(err) => println!(
+        "{} Could not restore branch {}: {}",
+        "!".red(),
+        branch.name,
+        err
+      ),
+    }
   }
 
   Ok(())
 }
 
-pub fn handle_sync_command(git: &GitManager) -> Result<(), Box<dyn std::error::Error>> {
-  println!("{}", "Syncing with remote...".blue());
+pub fn handle_sync_command(git: &GitManager, mode: SyncMode, json: bool) -> Result<()> {
+  let dry_run = mode == SyncMode::DryRun;
+  if json {
+    let statuses = git.sync_branches(mode)?;
+    let deleted = match mode {
+      SyncMode::Apply => statuses
+        .iter()
+        .filter_map(|status| match status {
+          BranchStatus::Merged(branch) => Some(branch.clone()),
+          _ => None,
+        })
+        .collect(),
+      SyncMode::Confirm => confirm_sync_deletions(git, &deletion_candidates(&statuses), true)?,
+      SyncMode::DryRun => Vec::new(),
+    };
+    return print_json(&serde_json::json!({
+      "dry_run": dry_run,
+      "statuses": statuses,
+      "deleted": deleted,
+    }));
+  }
+
+  if dry_run {
+    println!("{}", "Dry run, nothing will be changed...".blue());
+  } else {
+    println!("{}", "Syncing with remote...".blue());
+  }
 
-  let branch_statuses = git.sync_branches()?;
+  // Past tense for what was done, conditional for what a sync would do
+  let verb = |done: &'static str, planned: &'static str| if dry_run { planned } else { done };
+
+  let branch_statuses = git.sync_branches(mode)?;
+  let candidates = deletion_candidates(&branch_statuses);
   let mut has_updates = false;
+  let mut squash_merged = Vec::new();
 
   for status in branch_statuses {
     match status {
       BranchStatus::Updated(branch) => {
         has_updates = true;
         println!(
-          "{} Updated branch {} (fast-forward)",
+          "{} {} branch {} (fast-forward)",
           "✓".green(),
+          verb("Updated", "Would update"),
           branch.green()
         );
       }
+      // Left for the user to pick below
+      BranchStatus::Merged(_) if mode == SyncMode::Confirm => {
+        has_updates = true;
+      }
       BranchStatus::Merged(branch) => {
         has_updates = true;
-        println!("{} Deleted branch {} (was merged)", "✓".green(), branch);
+        println!(
+          "{} {} branch {} (was merged)",
+          "✓".green(),
+          verb("Deleted", "Would delete"),
+          branch
+        );
+      }
+      BranchStatus::Pruned(branch) => {
+        has_updates = true;
+        println!(
+          "{} {} remote branch {}",
+          "✓".green(),
+          verb("Pruned", "Would prune"),
+          branch
+        );
       }
       BranchStatus::RemoteGone(branch) => {
         has_updates = true;
@@ -142,9 +1123,27 @@ pub fn handle_sync_command(git: &GitManager) -> Result<(), Box<dyn std::error::E
           branch
         );
       }
-      BranchStatus::Diverged(branch) => {
+      // Behind its upstream only when it couldn't be fast-forwarded
+      BranchStatus::Behind(state) if state.tracking.upstream.is_some() => {
+        has_updates = true;
+        print_branch_state(&state, " (checked out in another worktree)");
+      }
+      BranchStatus::Behind(state) => {
+        has_updates = true;
+        print_branch_state(&state, "");
+      }
+      BranchStatus::Ahead(state) | BranchStatus::Diverged(state) => {
         has_updates = true;
-        println!("{} Branch {} has unpushed commits", "!".yellow(), branch);
+        print_branch_state(&state, "");
+      }
+      BranchStatus::SquashMerged(branch) => {
+        has_updates = true;
+        println!(
+          "{} Branch {} was squash-merged or rebased into the default branch",
+          "i".blue(),
+          branch
+        );
+        squash_merged.push(branch);
       }
       BranchStatus::LocalOnly(branch) => {
         println!("{} Branch {} is local only", "i".blue(), branch);
@@ -153,7 +1152,30 @@ pub fn handle_sync_command(git: &GitManager) -> Result<(), Box<dyn std::error::E
         has_updates = true;
         println!("{} Branch {} has uncommitted changes", "!".yellow(), branch);
       }
-      BranchStatus::UpToDate => (),
+      BranchStatus::UpToDate(_) => (),
+    }
+  }
+
+  if mode == SyncMode::Confirm {
+    confirm_sync_deletions(git, &candidates, false)?;
+  } else if !squash_merged.is_empty()
+    && !dry_run
+    && is_interactive()
+    && Confirm::with_theme(&*prompt_theme())
+      .with_prompt(format!(
+        "Delete {} squash-merged branches?\n  {}",
+        squash_merged.len().to_string().yellow().bold(),
+        squash_merged.join(", ")
+      ))
+      .interact()?
+  {
+    git.delete_branches(&squash_merged)?;
+    for branch in &squash_merged {
+      println!(
+        "{} Deleted branch {} (was squash-merged)",
+        "✓".green(),
+        branch
+      );
     }
   }
 
@@ -163,3 +1185,463 @@ pub fn handle_sync_command(git: &GitManager) -> Result<(), Box<dyn std::error::E
 
   Ok(())
 }
+
+/// Branches a sync may delete: name, reason and whether it is pre-selected.
+fn deletion_candidates(statuses: &[BranchStatus]) -> Vec<(String, &'static str, bool)> {
+  statuses
+    .iter()
+    .filter_map(|status| match status {
+      BranchStatus::Merged(branch) => Some((branch.clone(), "merged", true)),
+      BranchStatus::SquashMerged(branch) => Some((branch.clone(), "squash-merged", true)),
+      BranchStatus::RemoteGone(branch) => Some((branch.clone(), "deleted on remote", false)),
+      _ => None,
+    })
+    .collect()
+}
+
+/// Lets the user pick which of the sync's deletion candidates to remove and
+/// returns the deleted branches.
+fn confirm_sync_deletions(
+  git: &GitManager,
+  candidates: &[(String, &str, bool)],
+  json: bool,
+) -> Result<Vec<String>> {
+  if candidates.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  if !is_interactive() {
+    for (branch, reason, _) in candidates {
+      report(
+        json,
+        format!("{} Kept branch {} ({})", "i".blue(), branch, reason),
+      );
+    }
+    report(
+      json,
+      "Not running in a terminal, no branches were deleted.".to_string(),
+    );
+    return Ok(Vec::new());
+  }
+
+  let items: Vec<String> = candidates
+    .iter()
+    .map(|(branch, reason, _)| format!("{} <{}>", branch, reason))
+    .collect();
+  let checked: Vec<bool> = candidates.iter().map(|(_, _, checked)| *checked).collect();
+
+  let selections = match MultiSelect::with_theme(&*prompt_theme())
+    .with_prompt("Select the branches to delete")
+    .items(&items)
+    .defaults(&checked)
+    .interact_opt()?
+  {
+    Some(selections) => selections,
+    None => return Ok(Vec::new()),
+  };
+
+  let selected: Vec<String> = selections
+    .iter()
+    .map(|&i| candidates[i].0.clone())
+    .collect();
+  git.delete_branches(&selected)?;
+  for &i in &selections {
+    let (branch, reason, _) = &candidates[i];
+    report(
+      json,
+      format!("{} Deleted branch {} (was {})", "✓".green(), branch, reason),
+    );
+  }
+
+  Ok(selected)
+}
+
+/// Messages go to stderr when stdout carries JSON.
+fn report(json: bool, message: String) {
+  if json {
+    eprintln!("{}", message);
+  } else {
+    println!("{}", message);
+  }
+}
+
+/// Writes `value` to stdout as pretty-printed JSON.
+fn print_json(value: &impl Serialize) -> Result<()> {
+  let mut stdout = std::io::stdout();
+  serde_json::to_writer_pretty(&mut stdout, value).map_err(std::io::Error::from)?;
+  std::io::Write::write_all(&mut stdout, b"\n")?;
+  Ok(())
+}
+
+pub fn handle_worktree_list_command(git: &GitManager, json: bool) -> Result<()> {
+  let worktrees = git.get_worktree_list()?;
+  if json {
+    let entries: Vec<WorktreeEntry> = worktrees
+      .iter()
+      .map(|worktree| WorktreeEntry {
+        worktree,
+        dirty: !worktree.prunable && git.is_worktree_dirty(&worktree.path).unwrap_or(false),
+      })
+      .collect();
+    return print_json(&serde_json::json!({ "worktrees": entries }));
+  }
+
+  let toplevel = git.get_toplevel()?;
+  let path_width = worktrees.iter().map(|w| w.path.len()).max().unwrap_or(0);
+
+  for worktree in &worktrees {
+    let marker = if worktree.path == toplevel { "*" } else { " " };
+    let branch = match &worktree.branch {
+      Some(branch) => branch.magenta().to_string(),
+      None => "(detached)".yellow().to_string(),
+    };
+    let head: String = worktree.head.chars().take(7).collect();
+
+    let mut flags = Vec::new();
+    if worktree.prunable {
+      flags.push("stale".red().to_string());
+    } else if git.is_worktree_dirty(&worktree.path).unwrap_or(false) {
+      flags.push("dirty".yellow().to_string());
+    }
+    if worktree.locked {
+      flags.push("locked".blue().to_string());
+    }
+
+    println!(
+      "{} {:<path_width$} [{}] {} {}",
+      marker,
+      worktree.path,
+      head,
+      branch,
+      flags.join(", ")
+    );
+  }
+
+  Ok(())
+}
+
+pub fn handle_worktree_add_command(git: &GitManager) -> Result<()> {
+  let mut branches: Vec<BranchInfo> = git
+    .get_local_branches()?
+    .into_iter()
+    .filter(|branch| branch.worktree_path.is_none())
+    .collect();
+  git.sort_branches(&mut branches, BranchSort::Committed)?;
+
+  if branches.is_empty() {
+    return Err(SwagitError::NothingToPick(
+      "every branch already has a worktree".to_string(),
+    ));
+  }
+  if !is_interactive() {
+    return Err(SwagitError::NotInteractive(
+      "a terminal is needed to pick the branch for a worktree".to_string(),
+    ));
+  }
+
+  let selection = match FuzzySelect::with_theme(&*prompt_theme())
+    .with_prompt("Select the branch to create a worktree for")
+    .items(&branch_display_names(git, &branches))
+    .default(0)
+    .interact_opt()?
+  {
+    Some(selection) => selection,
+    None => return Ok(()),
+  };
+
+  let path = git.add_worktree(&branches[selection].name)?;
+  println!(
+    "{}",
+    format!(
+      "Created worktree for {} at {}",
+      branches[selection].name, path
+    )
+    .green()
+  );
+
+  Ok(())
+}
+
+pub fn handle_worktree_remove_command(git: &GitManager, force: bool) -> Result<()> {
+  let worktrees: Vec<WorktreeInfo> = git
+    .get_worktree_list()?
+    .into_iter()
+    .filter(|worktree| !worktree.is_main)
+    .collect();
+
+  if worktrees.is_empty() {
+    return Err(SwagitError::NothingToPick(
+      "no linked worktrees in the repository".to_string(),
+    ));
+  }
+  if !is_interactive() {
+    return Err(SwagitError::NotInteractive(
+      "a terminal is needed to pick the worktrees to remove".to_string(),
+    ));
+  }
+
+  let dirty: Vec<bool> = worktrees
+    .iter()
+    .map(|worktree| !worktree.prunable && git.is_worktree_dirty(&worktree.path).unwrap_or(false))
+    .collect();
+  let items: Vec<String> = worktrees
+    .iter()
+    .zip(&dirty)
+    .map(|(worktree, &dirty)| {
+      let mut display = format!(
+        "{} ({})",
+        worktree.path,
+        worktree.branch.as_deref().unwrap_or("detached")
+      );
+      if dirty {
+        display.push_str(" [dirty]");
+      }
+      if worktree.locked {
+        display.push_str(" [locked]");
+      }
+      display
+    })
+    .collect();
+
+  let selections = match MultiSelect::with_theme(&*prompt_theme())
+    .with_prompt("Select the worktrees to remove")
+    .items(&items)
+    .interact_opt()?
+  {
+    Some(selections) => selections,
+    None => return Ok(()),
+  };
+
+  if selections.is_empty() {
+    println!("No worktrees selected, exiting.");
+    return Ok(());
+  }
+
+  for i in selections {
+    let worktree = &worktrees[i];
+    if !force && (dirty[i] || worktree.locked) {
+      println!(
+        "{} Skipped {}, it is {} (use --force to remove it anyway)",
+        "!".yellow(),
+        worktree.path,
+        if dirty[i] { "dirty" } else { "locked" }
+      );
+      continue;
+    }
+
+    git.remove_worktree(worktree, force)?;
+    println!("{} Removed worktree {}", "✓".green(), worktree.path);
+  }
+
+  Ok(())
+}
+
+pub fn handle_worktree_prune_command(git: &GitManager) -> Result<()> {
+  let pruned = git.prune_worktrees()?;
+  if pruned.is_empty() {
+    println!("{}", "No stale worktrees to prune".green());
+  }
+  for path in pruned {
+    println!("{} Pruned stale worktree {}", "✓".green(), path);
+  }
+
+  Ok(())
+}
+
+/// Prints the resolved settings, each with the file or git config key it
+/// was read from.
+pub fn handle_config_command(git: &GitManager, json: bool) -> Result<()> {
+  let config = git.config();
+  let entries = config.entries();
+  if json {
+    let settings: serde_json::Map<String, serde_json::Value> = entries
+      .into_iter()
+      .map(|(name, value, source)| {
+        let setting = serde_json::json!({ "value": value, "source": source.to_string() });
+        (name.to_string(), setting)
+      })
+      .collect();
+    return print_json(&serde_json::json!({ "files": config.files, "settings": settings }));
+  }
+
+  let values: Vec<String> = entries
+    .iter()
+    .map(|(_, value, _)| match value {
+      serde_json::Value::Null => "-".to_string(),
+      serde_json::Value::String(value) => value.clone(),
+      serde_json::Value::Array(items) => items
+        .iter()
+        .filter_map(|item| item.as_str())
+        .collect::<Vec<_>>()
+        .join(", "),
+      other => other.to_string(),
+    })
+    .collect();
+  let name_width = entries
+    .iter()
+    .map(|(name, _, _)| name.len())
+    .max()
+    .unwrap_or(0);
+  let value_width = values.iter().map(|value| value.len()).max().unwrap_or(0);
+
+  for ((name, _, source), value) in entries.iter().zip(&values) {
+    println!(
+      "{:<name_width$} {:<value_width$} {}",
+      name,
+      value,
+      format!("({})", source).dimmed()
+    );
+  }
+  for file in &config.files {
+    let state = if file.exists() { "" } else { " (not found)" };
+    println!(
+      "{}",
+      format!("Looked in {}{}", file.display(), state).dimmed()
+    );
+  }
+
+  Ok(())
+}
+
+fn branch_display_names(git: &GitManager, branches: &[BranchInfo]) -> Vec<String> {
+  let columns = &git.config().columns.value;
+  // Remote-only branches are shown like `git branch -a` does
+  let display_name = |b: &BranchInfo| match b.remote {
+    Some(_) => format!("remotes/{}", b.name),
+    None => b.name.clone(),
+  };
+  let name_width = branches
+    .iter()
+    .map(|b| display_name(b).len())
+    .max()
+    .unwrap_or(0);
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+
+  branches
+    .iter()
+    .map(|b| {
+      let mut display = format!("{:<name_width$}", display_name(b));
+      let mut refs = Vec::new();
+      let mut commit = Vec::new();
+      for column in columns {
+        match column {
+          Column::Commit => refs.push(b.commit_id.clone()),
+          Column::Upstream => refs.extend(b.upstream.clone()),
+          Column::Age => commit.push(relative_time(now - b.commit_time)),
+          Column::Subject => commit.push(b.subject.clone()),
+          Column::Author | Column::Worktree => (),
+        }
+      }
+      if !refs.is_empty() {
+        display.push_str(&format!(" [{}]", refs.join(", ")));
+      }
+      if !commit.is_empty() {
+        display.push_str(&format!(" {}", commit.join(" - ")));
+      }
+      if columns.contains(&Column::Author) {
+        display.push_str(&format!(" ({})", b.author));
+      }
+      if let Some(worktree_path) = b
+        .worktree_path
+        .as_ref()
+        .filter(|_| columns.contains(&Column::Worktree))
+      {
+        if let Some(worktree_name) = std::path::Path::new(worktree_path).file_name() {
+          display.push_str(&format!(" ({})", worktree_name.to_string_lossy()));
+        }
+      }
+      display.trim_end().to_string()
+    })
+    .collect()
+}
+
+/// Prompts are plain when colors are off, e.g. with the `plain` theme or
+/// `NO_COLOR`.
+fn prompt_theme() -> Box<dyn Theme> {
+  if colored::control::SHOULD_COLORIZE.should_colorize() {
+    Box::new(ColorfulTheme::default())
+  } else {
+    Box::new(SimpleTheme)
+  }
+}
+
+/// Prints e.g. `feature-x: 3 ahead, 2 behind origin/feature-x` followed by
+/// the last commit and the suggested next step.
+fn print_branch_state(state: &BranchState, note: &str) {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+
+  println!(
+    "{} {}: {}{}",
+    "!".yellow(),
+    state.name,
+    tracking_summary(&state.tracking),
+    note
+  );
+  println!(
+    "    {} {} - {}",
+    state.commit_id,
+    relative_time(now - state.commit_time),
+    state.subject
+  );
+  let action = match state.tracking.suggested_action() {
+    Some(SuggestedAction::Push(remote)) => format!("push to {}", remote),
+    Some(SuggestedAction::Rebase(remote)) => format!("rebase onto {}", remote),
+    Some(SuggestedAction::Pull(remote)) => format!("pull from {}", remote),
+    None => return,
+  };
+  println!("    {} {}", "→".blue(), action);
+}
+
+/// Ahead and behind counts, e.g. `2 ahead, 1 behind upstream/main; 3 ahead
+/// of origin/feature`.
+fn tracking_summary(tracking: &Tracking) -> String {
+  let counts = |name: &str, ahead: usize, behind: usize| {
+    let name = name.trim_start_matches("refs/remotes/");
+    match (ahead, behind) {
+      (_, 0) => format!("{} ahead of {}", ahead, name),
+      (0, _) => format!("{} behind {}", behind, name),
+      (_, _) => format!("{} ahead, {} behind {}", ahead, behind, name),
+    }
+  };
+
+  let mut parts = Vec::new();
+  if let Some(upstream) = &tracking.upstream {
+    parts.push(counts(
+      upstream,
+      tracking.upstream_ahead,
+      tracking.upstream_behind,
+    ));
+  }
+  if let Some(push) = &tracking.push {
+    parts.push(counts(push, tracking.push_ahead, tracking.push_behind));
+  }
+  parts.join("; ")
+}
+
+/// Formats an age in seconds like "3 days ago".
+fn relative_time(seconds: i64) -> String {
+  const UNITS: [(i64, &str); 6] = [
+    (365 * 24 * 60 * 60, "year"),
+    (30 * 24 * 60 * 60, "month"),
+    (7 * 24 * 60 * 60, "week"),
+    (24 * 60 * 60, "day"),
+    (60 * 60, "hour"),
+    (60, "minute"),
+  ];
+
+  for (unit, name) in UNITS {
+    let count = seconds / unit;
+    if count > 0 {
+      let plural = if count == 1 { "" } else { "s" };
+      return format!("{} {}{} ago", count, name, plural);
+    }
+  }
+
+  "just now".to_string()
+}
@@ -1,69 +1,357 @@
+mod backend;
+mod config;
+mod error;
 mod git;
 mod handlers;
 
-use clap::{Arg, Command};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
 use colored::*;
+use config::{Mode, Theme};
 use dialoguer::console::Term;
-use git::GitManager;
-use handlers::{handle_checkout_command, handle_delete_command, handle_sync_command};
+use error::SwagitError;
+use git::{BranchSort, GitManager, SyncMode};
+use handlers::{
+  handle_checkout_command, handle_config_command, handle_delete_command, handle_list_command,
+  handle_new_command, handle_previous_command, handle_query_command, handle_rename_command,
+  handle_restore_command, handle_sync_command, handle_worktree_add_command,
+  handle_worktree_list_command, handle_worktree_prune_command, handle_worktree_remove_command,
+  CheckoutOptions, ListOptions,
+};
 use std::process;
 
 fn main() {
   if let Err(err) = ctrlc::set_handler(move || {
     let _ = Term::stdout().show_cursor();
-    process::exit(0);
+    process::exit(SwagitError::Cancelled.exit_code());
   }) {
     eprintln!("{}", format!("Error setting Ctrl-C handler: {}", err).red());
     process::exit(1);
   }
 
-  let matches = Command::new("swagit")
+  let mut command = Command::new("swagit")
     .version(env!("CARGO_PKG_VERSION"))
     .author(env!("CARGO_PKG_AUTHORS"))
     .about(env!("CARGO_PKG_DESCRIPTION"))
+    // Without a subcommand swagit runs the default mode, checkout unless
+    // configured otherwise, so its options are accepted here too
+    .args(checkout_args())
     .arg(
-      Arg::new("delete")
-        .short('d')
-        .long("delete")
-        .help("Select branches which you want to delete")
+      Arg::new("sort")
+        .long("sort")
+        .help("Order of branches in the picker [default: committed, or the sort setting]")
+        .value_parser(["committed", "checkout", "name"])
+        .global(true),
+    )
+    .arg(
+      Arg::new("recent")
+        .short('r')
+        .long("recent")
+        .help("List recently checked out branches first (same as --sort checkout)")
+        .conflicts_with("sort")
+        .global(true)
+        .action(clap::ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("all")
+        .short('a')
+        .long("all")
+        .help("Also list remote branches that have no local branch")
+        .global(true)
         .action(clap::ArgAction::SetTrue),
     )
     .arg(
-      Arg::new("sync")
-        .short('s')
-        .long("sync")
-        .help("Pull latest changes and cleanup merged branches")
+      Arg::new("json")
+        .long("json")
+        .help("Print branches, sync results and deleted branches as JSON instead of text")
+        .global(true)
         .action(clap::ArgAction::SetTrue),
     )
-    .get_matches();
+    .subcommand(
+      Command::new("checkout")
+        .about("Pick a branch and check it out (the default)")
+        .args(checkout_args()),
+    )
+    .subcommand(
+      Command::new("new")
+        .about("Create a branch, from a naming template if configured, and check it out")
+        .arg(Arg::new("name").help("Name of the branch, or the <slug> of a naming template"))
+        .arg(
+          Arg::new("print-path")
+            .long("print-path")
+            .help("Print the directory of the new branch for shell wrappers")
+            .conflicts_with("json")
+            .action(clap::ArgAction::SetTrue),
+        ),
+    )
+    .subcommand(
+      Command::new("rename")
+        .about("Rename a branch, and optionally its remote branch")
+        .arg(
+          Arg::new("branch")
+            .help("Branch to rename, the current one when only a new name is given"),
+        )
+        .arg(Arg::new("new-name").help("New name of the branch")),
+    )
+    .subcommand(
+      Command::new("delete")
+        .short_flag('d')
+        .long_flag("delete")
+        .about("Select branches which you want to delete"),
+    )
+    .subcommand(
+      Command::new("sync")
+        .short_flag('s')
+        .long_flag("sync")
+        .about("Pull latest changes and cleanup merged branches")
+        .arg(
+          Arg::new("dry-run")
+            .long("dry-run")
+            .help("Show what would be updated, pruned and deleted without changing anything")
+            .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+          Arg::new("interactive")
+            .short('i')
+            .long("interactive")
+            .help("Pick which merged, squash-merged and remote-gone branches to delete")
+            .conflicts_with("dry-run")
+            .action(clap::ArgAction::SetTrue),
+        ),
+    )
+    .subcommand(
+      Command::new("list")
+        .about("Print the branches with their upstream, ahead/behind counts and merge state")
+        .arg(Arg::new("pattern").help("Only branches matching this pattern, * and ? are wildcards"))
+        .arg(
+          Arg::new("merged")
+            .long("merged")
+            .help("Only branches merged or squash-merged into the default branch")
+            .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+          Arg::new("no-merged")
+            .long("no-merged")
+            .help("Only branches not merged into the default branch")
+            .conflicts_with("merged")
+            .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+          Arg::new("gone")
+            .long("gone")
+            .help("Only branches whose upstream was deleted on the remote")
+            .action(clap::ArgAction::SetTrue),
+        ),
+    )
+    .subcommand(
+      Command::new("undo")
+        .long_flag("restore")
+        .about("Select deleted branches to restore"),
+    )
+    .subcommand(
+      Command::new("config").about("Show the effective settings and where each one comes from"),
+    )
+    .subcommand(
+      Command::new("worktree")
+        .about("List and manage worktrees")
+        .subcommand(Command::new("list").about("List worktrees with their branch and state"))
+        .subcommand(Command::new("add").about("Create a worktree for a branch"))
+        .subcommand(
+          Command::new("remove")
+            .about("Select worktrees to remove")
+            .arg(
+              Arg::new("force")
+                .short('f')
+                .long("force")
+                .help("Also remove dirty and locked worktrees")
+                .action(clap::ArgAction::SetTrue),
+            ),
+        )
+        .subcommand(
+          Command::new("prune").about("Remove entries of worktrees whose directory is gone"),
+        ),
+    );
+  let matches = match command.try_get_matches_from_mut(std::env::args_os()) {
+    Ok(matches) => matches,
+    Err(err) if err.kind() == ErrorKind::UnknownArgument => {
+      // `-d -s` parses as the delete subcommand with an unknown `-s`
+      let flags = command_flags(std::env::args());
+      if let [first, second, ..] = flags.as_slice() {
+        let message = format!("'{}' cannot be used with '{}'", first, second);
+        command.error(ErrorKind::ArgumentConflict, message).exit();
+      }
+      err.exit()
+    }
+    Err(err) => err.exit(),
+  };
+
+  // The global options may go on either side of a subcommand, but those of
+  // the default mode only make sense without one
+  if let Some(subcommand) = matches.subcommand_name() {
+    for (arg, name) in [("print-path", "--print-path"), ("target", "[target]")] {
+      if matches.value_source(arg) == Some(ValueSource::CommandLine) {
+        let message = format!(
+          "the subcommand '{}' cannot be used with '{}'",
+          subcommand, name
+        );
+        command.error(ErrorKind::ArgumentConflict, message).exit();
+      }
+    }
+  }
 
   let git = match GitManager::new() {
     Ok(git) => git,
-    Err(_) => {
-      eprintln!("{}", "Error: not a git repository".red());
-      process::exit(1);
-    }
+    Err(err) => exit_with(err),
   };
+  if git.config().theme.value == Theme::Plain {
+    colored::control::set_override(false);
+  }
 
-  match git.get_current_branch() {
-    Ok(branch) => println!("{} Current branch is {}", "Info:".blue(), branch.magenta()),
-    Err(_) => {
-      eprintln!("{}", "Error: could not get current branch".red());
-      process::exit(1);
-    }
+  // Options of the checkout subcommand, or of swagit itself without one
+  let checkout = match matches.subcommand() {
+    Some(("checkout", checkout)) | Some(("new", checkout)) => checkout,
+    _ => &matches,
+  };
+  let print_path = checkout.get_flag("print-path");
+  let target = match matches.subcommand_name() {
+    Some("new") => None,
+    _ => checkout.get_one::<String>("target").map(String::as_str),
+  };
+  let json = matches.get_flag("json");
+  let info = match git.get_current_branch() {
+    Ok(branch) if branch.is_empty() => format!("{} HEAD is detached", "Info:".blue()),
+    Ok(branch) => format!("{} Current branch is {}", "Info:".blue(), branch.magenta()),
+    Err(err) => exit_with(err),
+  };
+  if print_path || json {
+    eprintln!("{}", info);
+  } else {
+    println!("{}", info);
   }
 
-  let result = match (matches.get_flag("delete"), matches.get_flag("sync")) {
-    (true, _) => handle_delete_command(&git),
-    (_, true) => handle_sync_command(&git),
-    _ => handle_checkout_command(&git),
+  let sort = if matches.get_flag("recent") {
+    BranchSort::CheckedOut
+  } else {
+    match matches.get_one::<String>("sort").map(String::as_str) {
+      Some("checkout") => BranchSort::CheckedOut,
+      Some("name") => BranchSort::Name,
+      Some(_) => BranchSort::Committed,
+      None => git.config().sort.value,
+    }
+  };
+
+  let options = CheckoutOptions {
+    sort,
+    all: matches.get_flag("all"),
+    print_path,
+    json,
+  };
+  let list_options = ListOptions {
+    sort,
+    all: options.all,
+    pattern: None,
+    merged: None,
+    gone: false,
+    json,
+  };
+
+  let result = match matches.subcommand() {
+    Some(("worktree", worktree)) => match worktree.subcommand() {
+      Some(("add", _)) => handle_worktree_add_command(&git),
+      Some(("remove", remove)) => handle_worktree_remove_command(&git, remove.get_flag("force")),
+      Some(("prune", _)) => handle_worktree_prune_command(&git),
+      _ => handle_worktree_list_command(&git, json),
+    },
+    Some(("list", list)) => {
+      let merged = match (list.get_flag("merged"), list.get_flag("no-merged")) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+      };
+      let options = ListOptions {
+        pattern: list.get_one::<String>("pattern").cloned(),
+        merged,
+        gone: list.get_flag("gone"),
+        ..list_options
+      };
+      handle_list_command(&git, &options)
+    }
+    Some(("new", new)) => {
+      let name = new.get_one::<String>("name").map(String::as_str);
+      handle_new_command(&git, &options, name)
+    }
+    Some(("rename", rename)) => {
+      let branch = rename.get_one::<String>("branch").map(String::as_str);
+      let new_name = rename.get_one::<String>("new-name").map(String::as_str);
+      // A single name is the new name of the current branch
+      match (branch, new_name) {
+        (Some(new_name), None) => handle_rename_command(&git, None, Some(new_name)),
+        (branch, new_name) => handle_rename_command(&git, branch, new_name),
+      }
+    }
+    Some(("config", _)) => handle_config_command(&git, json),
+    Some(("undo", _)) => handle_restore_command(&git),
+    Some(("delete", _)) => handle_delete_command(&git, json),
+    Some(("sync", sync)) => handle_sync_command(&git, sync_mode(sync), json),
+    _ if target == Some("-") => handle_previous_command(&git, &options),
+    _ if target.is_some() => handle_query_command(&git, &options, target.unwrap_or_default()),
+    Some(("checkout", _)) => handle_checkout_command(&git, &options),
+    _ => match git.config().mode.value {
+      Mode::Checkout => handle_checkout_command(&git, &options),
+      Mode::List => handle_list_command(&git, &list_options),
+      Mode::Sync => handle_sync_command(&git, SyncMode::Apply, json),
+    },
   };
 
   if let Err(err) = result {
-    if !err.to_string().contains("read interrupted") {
-      eprintln!("{}", format!("Error: {}", err).red());
-      process::exit(1);
+    exit_with(err);
+  }
+}
+
+/// Options of `swagit checkout`, also accepted by `swagit` on its own.
+fn checkout_args() -> [Arg; 2] {
+  [
+    Arg::new("print-path")
+      .long("print-path")
+      .help("Print the directory of the selected branch (its worktree if it has one) for shell wrappers")
+      .conflicts_with("json")
+      .action(clap::ArgAction::SetTrue),
+    Arg::new("target")
+      .help("Branch to check out, matched like in the picker, or - for the previously checked out one"),
+  ]
+}
+
+/// The distinct command flags (`--delete`, `--sync`) among `args`, in the
+/// order they were given.
+fn command_flags(args: impl Iterator<Item = String>) -> Vec<&'static str> {
+  let mut flags = Vec::new();
+  for arg in args.skip(1).take_while(|arg| arg != "--") {
+    let flag = match arg.as_str() {
+      "-d" | "--delete" => "--delete",
+      "-s" | "--sync" => "--sync",
+      _ => continue,
+    };
+    if !flags.contains(&flag) {
+      flags.push(flag);
     }
-    process::exit(0);
   }
+  flags
+}
+
+fn sync_mode(sync: &ArgMatches) -> SyncMode {
+  if sync.get_flag("dry-run") {
+    SyncMode::DryRun
+  } else if sync.get_flag("interactive") {
+    SyncMode::Confirm
+  } else {
+    SyncMode::Apply
+  }
+}
+
+fn exit_with(err: SwagitError) -> ! {
+  if !matches!(err, SwagitError::Cancelled) {
+    eprintln!("{}", format!("Error: {}", err).red());
+  }
+  process::exit(err.exit_code());
 }
@@ -54,6 +54,25 @@ fn setup_git_repo() -> TempDir {
   temp_dir
 }
 
+/// Runs git in `dir` to set up a test, e.g. `let git = git_in(&temp_dir);`.
+fn git_in(dir: &TempDir) -> impl Fn(&[&str]) -> std::process::Output + '_ {
+  move |args: &[&str]| {
+    StdCommand::new("git")
+      .args(args)
+      .current_dir(dir)
+      .output()
+      .unwrap()
+  }
+}
+
+/// A bare repository added as remote `name` of the repository in `temp_dir`.
+fn setup_remote(temp_dir: &TempDir, name: &str) -> TempDir {
+  let remote_dir = TempDir::new().unwrap();
+  git_in(&remote_dir)(&["init", "--bare"]);
+  git_in(temp_dir)(&["remote", "add", name, remote_dir.path().to_str().unwrap()]);
+  remote_dir
+}
+
 #[test]
 fn test_not_git_repo() {
   let temp_dir = TempDir::new().unwrap();
@@ -63,6 +82,7 @@ fn test_not_git_repo() {
     .current_dir(&temp_dir)
     .assert()
     .failure()
+    .code(3)
     .stderr(predicate::str::contains("not a git repository"));
 }
 
@@ -91,7 +111,15 @@ fn test_current_branch() {
     .assert()
     .success()
     .stdout(predicate::str::contains("Current branch is main"))
-    .stdout(predicate::str::contains("Switched to branch test-branch")); // Automatically switched to the first branch in non-terminal environments
+    .stdout(predicate::str::contains("test-branch")) // Branches are listed in non-terminal environments
+    .stderr(predicate::str::contains("no branch was checked out"));
+
+  let output = StdCommand::new("git")
+    .args(["branch", "--show-current"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "main");
 }
 
 #[test]
@@ -139,6 +167,7 @@ fn test_sync_with_no_remote() {
     .arg("-s")
     .assert()
     .failure()
+    .code(5)
     .stderr(predicate::str::contains("No remote repository configured"));
 }
 
@@ -231,3 +260,1545 @@ fn test_sync_does_not_delete_current_branch() {
   
   assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "test-branch");
 }
+
+#[test]
+fn test_cli_backend_fallback() {
+  let temp_dir = setup_git_repo();
+
+  for args in [
+    ["checkout", "-b", "test-branch"],
+    ["checkout", "main", "--quiet"],
+  ] {
+    StdCommand::new("git")
+      .args(args)
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .env("SWAGIT_BACKEND", "cli")
+    .arg("-")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Current branch is main"))
+    .stdout(predicate::str::contains("Switched to branch test-branch"));
+
+  let output = StdCommand::new("git")
+    .args(["branch", "--show-current"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "test-branch");
+}
+
+#[test]
+fn test_backends_report_pruned_upstream() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["checkout", "-b", "gone"]);
+  git(&["push", "-u", "origin", "gone"]);
+  git(&["checkout", "main"]);
+  git(&["push", "origin", "--delete", "gone"]);
+  git(&["fetch", "--prune"]);
+
+  let list = |backend: &str| {
+    let output = Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .env("SWAGIT_BACKEND", backend)
+      .args(["list", "--json"])
+      .output()
+      .unwrap();
+    String::from_utf8_lossy(&output.stdout).to_string()
+  };
+
+  let libgit2 = list("libgit2");
+  assert!(libgit2.contains("\"upstream\": \"origin/gone\""));
+  assert_eq!(libgit2, list("cli"));
+}
+
+#[test]
+fn test_checkout_runs_filters_and_hooks() {
+  let temp_dir = setup_git_repo();
+
+  let git = git_in(&temp_dir);
+
+  git(&["config", "filter.test.smudge", "sed s/X/SMUDGED/"]);
+  git(&["config", "filter.test.clean", "cat"]);
+  git(&["checkout", "-b", "test-branch"]);
+  std::fs::write(
+    temp_dir.path().join(".gitattributes"),
+    "*.dat filter=test\n",
+  )
+  .unwrap();
+  std::fs::write(temp_dir.path().join("file.dat"), "X\n").unwrap();
+  git(&["add", ".gitattributes", "file.dat"]);
+  git(&["commit", "-m", "filtered file"]);
+  git(&["checkout", "main", "--quiet"]);
+
+  let hook = temp_dir.path().join(".git/hooks/post-checkout");
+  std::fs::write(&hook, "#!/bin/sh\ntouch post-checkout-ran\n").unwrap();
+  StdCommand::new("chmod")
+    .args(["+x", hook.to_str().unwrap()])
+    .output()
+    .unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Switched to branch test-branch"));
+
+  let content = std::fs::read_to_string(temp_dir.path().join("file.dat")).unwrap();
+  assert_eq!(content.trim(), "SMUDGED");
+  assert!(temp_dir.path().join("post-checkout-ran").exists());
+}
+
+#[test]
+fn test_checkout_conflict_exit_code() {
+  let temp_dir = setup_git_repo();
+
+  StdCommand::new("git")
+    .args(["checkout", "-b", "test-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  std::fs::write(temp_dir.path().join("README.md"), "branch").unwrap();
+  StdCommand::new("git")
+    .args(["commit", "-am", "branch commit"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["checkout", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Uncommitted change to a file that differs on test-branch
+  std::fs::write(temp_dir.path().join("README.md"), "local").unwrap();
+
+  for backend in ["libgit2", "cli"] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .env("SWAGIT_BACKEND", backend)
+      .arg("-")
+      .assert()
+      .failure()
+      .code(6);
+  }
+}
+
+#[test]
+fn test_checkout_conflict_on_renamed_file() {
+  let temp_dir = setup_git_repo();
+
+  let git = git_in(&temp_dir);
+
+  let content: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+  std::fs::write(temp_dir.path().join("old.txt"), &content).unwrap();
+  git(&["add", "old.txt"]);
+  git(&["commit", "-m", "add old.txt"]);
+  git(&["checkout", "-b", "test-branch"]);
+  git(&["mv", "old.txt", "new.txt"]);
+  git(&["commit", "-m", "rename old.txt"]);
+  git(&["checkout", "main", "--quiet"]);
+
+  // The file is gone on test-branch, so the change would be lost
+  std::fs::write(temp_dir.path().join("old.txt"), content + "local\n").unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-")
+    .assert()
+    .code(6)
+    .stderr(predicate::str::contains(
+      "would be overwritten by checkout:\n  old.txt",
+    ));
+}
+
+#[test]
+fn test_checkout_sort_order() {
+  let temp_dir = setup_git_repo();
+
+  StdCommand::new("git")
+    .args(["checkout", "-b", "a-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["commit", "--allow-empty", "-m", "old commit"])
+    .env("GIT_COMMITTER_DATE", "2020-01-01T00:00:00")
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["checkout", "-b", "b-branch", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["commit", "--allow-empty", "-m", "new commit"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["checkout", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Most recently committed first by default
+  for (args, first, second) in [
+    (&["list"][..], "b-branch", "a-branch"),
+    (&["list", "--sort", "name"][..], "a-branch", "b-branch"),
+  ] {
+    let output = Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .output()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.find(first).unwrap() < stdout.find(second).unwrap());
+  }
+}
+
+#[test]
+fn test_switch_to_previous_branch() {
+  let temp_dir = setup_git_repo();
+
+  for args in [["branch", "a-branch"], ["branch", "b-branch"]] {
+    StdCommand::new("git")
+      .args(args)
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+  for branch in ["b-branch", "main"] {
+    StdCommand::new("git")
+      .args(["checkout", branch])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Switched to branch b-branch"));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Switched to branch main"));
+}
+
+#[test]
+fn test_checkout_remote_only_branch() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["branch", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["push", "origin", "main", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["branch", "-D", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  for backend in ["libgit2", "cli"] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .env("SWAGIT_BACKEND", backend)
+      .args(["list", "--all"])
+      .assert()
+      .success()
+      .stdout(predicate::str::contains("remotes/origin/feature"));
+
+    // Checking it out by name creates a local branch tracking it
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .env("SWAGIT_BACKEND", backend)
+      .args(["--all", "feature"])
+      .assert()
+      .success();
+
+    let upstream = StdCommand::new("git")
+      .args(["rev-parse", "--abbrev-ref", "feature@{upstream}"])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    assert_eq!(
+      String::from_utf8_lossy(&upstream.stdout).trim(),
+      "origin/feature"
+    );
+
+    let current = StdCommand::new("git")
+      .args(["branch", "--show-current"])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    assert_eq!(String::from_utf8_lossy(&current.stdout).trim(), "feature");
+
+    StdCommand::new("git")
+      .args(["checkout", "main"])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    StdCommand::new("git")
+      .args(["branch", "-D", "feature"])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+}
+
+#[test]
+fn test_checkout_by_query() {
+  let temp_dir = setup_git_repo();
+
+  let git = git_in(&temp_dir);
+  let current = || {
+    String::from_utf8_lossy(&git(&["branch", "--show-current"]).stdout)
+      .trim()
+      .to_string()
+  };
+
+  for branch in ["feature/login-form", "feature/logout", "bugfix/crash"] {
+    git(&["branch", branch]);
+  }
+
+  // A unique fuzzy match is checked out right away
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("crsh")
+    .assert()
+    .success();
+  assert_eq!(current(), "bugfix/crash");
+
+  // Without a terminal an ambiguous query doesn't pick one
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("log")
+    .assert()
+    .code(13)
+    .stderr(predicate::str::contains("matches 2 branches"))
+    .stderr(predicate::str::contains("feature/login-form"));
+  assert_eq!(current(), "bugfix/crash");
+
+  // An exact name wins over other matches
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["checkout", "feature/logout"])
+    .assert()
+    .success();
+  assert_eq!(current(), "feature/logout");
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("nothing-like-it")
+    .assert()
+    .code(12)
+    .stderr(predicate::str::contains(
+      "no branch matches 'nothing-like-it'",
+    ));
+}
+
+#[test]
+fn test_restore_parked_changes() {
+  let temp_dir = setup_git_repo();
+
+  StdCommand::new("git")
+    .args(["checkout", "-b", "test-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Changes parked on main by an earlier switch
+  std::fs::write(temp_dir.path().join("README.md"), "parked").unwrap();
+  StdCommand::new("git")
+    .args(["stash", "push", "-m", "swagit-park:main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Switched to branch main"))
+    .stdout(predicate::str::contains("Restored changes parked on main"));
+
+  let readme = std::fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+  assert_eq!(readme, "parked");
+
+  let output = StdCommand::new("git")
+    .args(["stash", "list"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_print_worktree_path() {
+  let temp_dir = setup_git_repo();
+  let worktree_dir = TempDir::new().unwrap();
+  let worktree_path = worktree_dir.path().join("test-branch");
+
+  for args in [
+    ["checkout", "-b", "test-branch"],
+    ["checkout", "main", "--quiet"],
+  ] {
+    StdCommand::new("git")
+      .args(args)
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+  StdCommand::new("git")
+    .args(["worktree", "add"])
+    .arg(&worktree_path)
+    .arg("test-branch")
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["-", "--print-path"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+
+  let printed = String::from_utf8_lossy(&output.stdout);
+  assert_eq!(
+    std::fs::canonicalize(printed.trim()).unwrap(),
+    std::fs::canonicalize(&worktree_path).unwrap()
+  );
+
+  // The branch stays checked out in its worktree
+  let output = StdCommand::new("git")
+    .args(["branch", "--show-current"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "main");
+}
+
+#[test]
+fn test_worktree_list_and_prune() {
+  let temp_dir = setup_git_repo();
+  let worktree_dir = TempDir::new().unwrap();
+  let locked_path = worktree_dir.path().join("locked");
+  let stale_path = worktree_dir.path().join("stale");
+
+  StdCommand::new("git")
+    .args(["worktree", "add", "--lock", "-b", "locked-branch"])
+    .arg(&locked_path)
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["worktree", "add", "-b", "stale-branch"])
+    .arg(&stale_path)
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  std::fs::remove_dir_all(&stale_path).unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "list"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("locked-branch locked"))
+    .stdout(predicate::str::contains("stale-branch stale"));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "prune"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Pruned stale worktree"));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "list"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("stale-branch").not());
+}
+
+#[test]
+fn test_nothing_to_pick() {
+  let temp_dir = setup_git_repo();
+
+  for (args, error) in [
+    (vec!["-"], "no previous branch to switch to"),
+    (vec!["undo"], "no deleted branches to restore"),
+    (vec!["worktree", "remove"], "no linked worktrees"),
+  ] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .assert()
+      .code(14)
+      .stderr(predicate::str::contains(error));
+  }
+}
+
+#[test]
+fn test_worktree_pickers_without_terminal() {
+  let temp_dir = setup_git_repo();
+  let worktree_dir = TempDir::new().unwrap();
+
+  StdCommand::new("git")
+    .args(["branch", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["worktree", "add", "-b", "linked"])
+    .arg(worktree_dir.path().join("linked"))
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Stdin is not a terminal, so the pickers must not be opened
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "add"])
+    .write_stdin("")
+    .timeout(std::time::Duration::from_secs(10))
+    .assert()
+    .code(2)
+    .stderr(predicate::str::contains("a terminal is needed"));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "remove"])
+    .write_stdin("")
+    .timeout(std::time::Duration::from_secs(10))
+    .assert()
+    .code(2)
+    .stderr(predicate::str::contains("a terminal is needed"));
+}
+
+#[test]
+fn test_sync_journals_deleted_branches() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["branch", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  let output = StdCommand::new("git")
+    .args(["rev-parse", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  let commit_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Deleted branch merged-branch"));
+
+  let journal =
+    std::fs::read_to_string(temp_dir.path().join(".git/swagit/deleted-branches")).unwrap();
+  assert!(journal.contains(&format!("{}\tmerged-branch", commit_id)));
+}
+
+#[test]
+fn test_sync_keeps_branches_it_cannot_journal() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["branch", "merged-branch"]);
+
+  // A file where the journal directory goes makes writing the journal fail
+  std::fs::write(temp_dir.path().join(".git/swagit"), "").unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Deleted branch merged-branch").not());
+
+  let branch = git(&["rev-parse", "--verify", "merged-branch"]);
+  assert!(branch.status.success());
+}
+
+#[test]
+fn test_sync_keeps_protected_branches() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["config", "swagit.protected", "release/*"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  for branch in ["release/1.0", "merged-branch"] {
+    StdCommand::new("git")
+      .args(["branch", branch])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+
+  // Work on a branch that is not merged into main, branches merged into it
+  // but not into main must survive
+  StdCommand::new("git")
+    .args(["checkout", "-b", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["commit", "--allow-empty", "-m", "feature work"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["branch", "feature-part"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Deleted branch merged-branch"));
+
+  let output = StdCommand::new("git")
+    .args(["branch", "--format=%(refname:short)"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  let branches = String::from_utf8_lossy(&output.stdout);
+  assert!(branches.lines().any(|b| b == "release/1.0"));
+  assert!(branches.lines().any(|b| b == "feature-part"));
+  assert!(branches.lines().any(|b| b == "main"));
+  assert!(!branches.lines().any(|b| b == "merged-branch"));
+}
+
+#[test]
+fn test_sync_reports_squash_merged_branches() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["checkout", "-b", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  for (file, content) in [("a.txt", "a"), ("b.txt", "b")] {
+    std::fs::write(temp_dir.path().join(file), content).unwrap();
+    StdCommand::new("git")
+      .args(["add", file])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    StdCommand::new("git")
+      .args(["commit", "-m", file])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+  }
+
+  // Squash-merge feature into main like a pull request would
+  StdCommand::new("git")
+    .args(["checkout", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["merge", "--squash", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["commit", "-m", "Feature (#1)"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Checking for the squash doesn't write to the object store
+  let count_objects = || {
+    let output = StdCommand::new("git")
+      .args(["count-objects"])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    String::from_utf8_lossy(&output.stdout).to_string()
+  };
+  let before = count_objects();
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("list")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("squash-merged"));
+  assert_eq!(count_objects(), before);
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Branch feature was squash-merged or rebased into the default branch",
+    ));
+}
+
+#[test]
+fn test_sync_reports_squash_merged_branches_with_stale_default_branch() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["checkout", "-b", "feat-a"]);
+  std::fs::write(temp_dir.path().join("a.txt"), "a").unwrap();
+  git(&["add", "a.txt"]);
+  git(&["commit", "-m", "a.txt"]);
+  git(&["commit", "--allow-empty", "-m", "polish"]);
+
+  // feat-a lands on origin/main as a squash, the local main stays behind
+  git(&["checkout", "-b", "squash", "main"]);
+  git(&["merge", "--squash", "feat-a"]);
+  git(&["commit", "-m", "Feature A (#1)"]);
+  git(&["push", "origin", "squash:main"]);
+  git(&["checkout", "-b", "work", "main"]);
+  git(&["branch", "-D", "squash"]);
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Branch feat-a was squash-merged or rebased into the default branch",
+    ));
+}
+
+#[test]
+fn test_sync_dry_run_changes_nothing() {
+  let temp_dir = setup_git_repo();
+  let remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["branch", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Deleted on the remote but still known locally
+  StdCommand::new("git")
+    .args(["branch", "-D", "merged-branch"])
+    .current_dir(&remote_dir)
+    .output()
+    .unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["-s", "--dry-run"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Would delete branch merged-branch",
+    ))
+    .stdout(predicate::str::contains(
+      "Would prune remote branch origin/merged-branch",
+    ));
+
+  for refname in [
+    "refs/heads/merged-branch",
+    "refs/remotes/origin/merged-branch",
+  ] {
+    let output = StdCommand::new("git")
+      .args(["rev-parse", "--verify", refname])
+      .current_dir(&temp_dir)
+      .output()
+      .unwrap();
+    assert!(output.status.success(), "{} was removed", refname);
+  }
+  assert!(!temp_dir
+    .path()
+    .join(".git/swagit/deleted-branches")
+    .exists());
+}
+
+#[test]
+fn test_sync_dry_run_matches_apply() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let worktree_dir = TempDir::new().unwrap();
+  let worktree_path = worktree_dir.path().join("wtb");
+
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["branch", "merged-branch"]);
+  // Merged too, but git won't delete a branch checked out in a worktree
+  git(&[
+    "worktree",
+    "add",
+    "-b",
+    "wtb",
+    worktree_path.to_str().unwrap(),
+  ]);
+
+  let sync = |args: &[&str]| {
+    let output = Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .output()
+      .unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).to_string()
+  };
+  let count_objects = || String::from_utf8_lossy(&git(&["count-objects"]).stdout).to_string();
+
+  let before = count_objects();
+  let dry_run = sync(&["-s", "--dry-run"]);
+  assert_eq!(count_objects(), before);
+  let applied = sync(&["-s"]);
+
+  assert!(dry_run.contains("Would delete branch merged-branch"));
+  assert!(applied.contains("Deleted branch merged-branch"));
+  let about_wtb = |output: &str| -> Vec<String> {
+    output
+      .lines()
+      .filter(|line| line.contains("wtb"))
+      .map(|line| line.to_string())
+      .collect()
+  };
+  assert!(!dry_run.contains("Would delete branch wtb"));
+  assert_eq!(about_wtb(&dry_run), about_wtb(&applied));
+  assert!(git(&["rev-parse", "--verify", "refs/heads/wtb"])
+    .status
+    .success());
+}
+
+#[test]
+fn test_interactive_sync_keeps_branches_without_terminal() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["branch", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["-s", "--interactive"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Kept branch merged-branch (merged)",
+    ));
+
+  let output = StdCommand::new("git")
+    .args(["branch", "--list", "merged-branch"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert!(String::from_utf8_lossy(&output.stdout).contains("merged-branch"));
+}
+
+#[test]
+fn test_sync_fast_forwards_branches_behind_upstream() {
+  let temp_dir = setup_git_repo();
+  let remote_dir = setup_remote(&temp_dir, "origin");
+  let other_dir = TempDir::new().unwrap();
+
+  StdCommand::new("git")
+    .args(["checkout", "-b", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  std::fs::write(temp_dir.path().join("feature.txt"), "feature").unwrap();
+  StdCommand::new("git")
+    .args(["add", "feature.txt"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["commit", "-m", "feature commit"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["push", "-u", "origin", "main", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  StdCommand::new("git")
+    .args(["checkout", "main"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Someone else pushes to feature
+  StdCommand::new("git")
+    .args([
+      "clone",
+      "-b",
+      "feature",
+      remote_dir.path().to_str().unwrap(),
+      other_dir.path().to_str().unwrap(),
+    ])
+    .output()
+    .unwrap();
+  for args in [
+    &["config", "user.name", "Other User"][..],
+    &["config", "user.email", "other@example.com"][..],
+    &["commit", "--allow-empty", "-m", "remote commit"][..],
+    &["push", "origin", "feature"][..],
+  ] {
+    StdCommand::new("git")
+      .args(args)
+      .current_dir(&other_dir)
+      .output()
+      .unwrap();
+  }
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Updated branch feature"));
+
+  let output = StdCommand::new("git")
+    .args(["log", "-1", "--format=%s", "feature"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert_eq!(
+    String::from_utf8_lossy(&output.stdout).trim(),
+    "remote commit"
+  );
+
+  let output = StdCommand::new("git")
+    .args(["branch", "--show-current"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "main");
+}
+
+#[test]
+fn test_sync_uses_configured_upstream_and_push_remote() {
+  let temp_dir = setup_git_repo();
+  // Pull from upstream, push to the fork, and no remote called origin
+  let _upstream_dir = setup_remote(&temp_dir, "upstream");
+  let _fork_dir = setup_remote(&temp_dir, "fork");
+
+  let git = git_in(&temp_dir);
+  let commit = |file: &str| {
+    std::fs::write(temp_dir.path().join(file), file).unwrap();
+    git(&["add", file]);
+    git(&["commit", "-m", file]);
+  };
+
+  git(&["config", "remote.pushDefault", "fork"]);
+  git(&["config", "push.default", "current"]);
+  git(&["push", "-u", "upstream", "main"]);
+
+  // topic tracks a branch of another name and falls behind it
+  git(&["checkout", "-b", "release"]);
+  commit("release-1.txt");
+  git(&["push", "upstream", "release"]);
+  git(&["branch", "topic", "--track", "upstream/release"]);
+  commit("release-2.txt");
+  git(&["push", "upstream", "release"]);
+  git(&["checkout", "main"]);
+  git(&["branch", "-D", "release"]);
+
+  // work pulls from upstream/main and is partly pushed to the fork
+  git(&["checkout", "-b", "work", "--track", "upstream/main"]);
+  commit("work-1.txt");
+  git(&["push", "fork", "work"]);
+  commit("work-2.txt");
+  git(&["checkout", "main"]);
+  commit("main.txt");
+  git(&["push", "upstream", "main"]);
+
+  // fork-only has no upstream and its push branch got a new commit
+  git(&["checkout", "-b", "fork-only"]);
+  commit("fork-1.txt");
+  commit("fork-2.txt");
+  git(&["push", "fork", "fork-only"]);
+  git(&["reset", "--hard", "HEAD~1"]);
+  git(&["checkout", "main"]);
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "work: 2 ahead, 1 behind upstream/main; 1 ahead of fork/work",
+    ))
+    .stdout(predicate::str::contains("rebase onto upstream/main"))
+    .stdout(predicate::str::contains("Updated branch topic"))
+    .stdout(predicate::str::contains(
+      "fork-only: 1 behind fork/fork-only\n",
+    ))
+    .stdout(predicate::str::contains("pull from fork/fork-only"));
+
+  let output = StdCommand::new("git")
+    .args(["log", "-1", "--format=%s", "topic"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+  assert_eq!(
+    String::from_utf8_lossy(&output.stdout).trim(),
+    "release-2.txt"
+  );
+}
+
+#[test]
+fn test_sync_tells_gone_branches_from_local_only() {
+  let temp_dir = setup_git_repo();
+  let remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+  let commit = |file: &str| {
+    std::fs::write(temp_dir.path().join(file), file).unwrap();
+    git(&["add", file]);
+    git(&["commit", "-m", file]);
+  };
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["checkout", "-b", "gone-branch"]);
+  commit("gone.txt");
+  git(&["push", "-u", "origin", "gone-branch"]);
+  git(&["checkout", "-b", "local-branch", "main"]);
+  commit("local.txt");
+  git(&["checkout", "main"]);
+
+  StdCommand::new("git")
+    .args(["branch", "-D", "gone-branch"])
+    .current_dir(&remote_dir)
+    .output()
+    .unwrap();
+
+  for args in [&["-s", "--dry-run"][..], &["-s"][..]] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .assert()
+      .success()
+      .stdout(predicate::str::contains(
+        "Branch gone-branch was deleted on remote but not merged",
+      ))
+      .stdout(predicate::str::contains(
+        "Branch local-branch is local only",
+      ));
+  }
+}
+
+#[test]
+fn test_sync_reports_counts_and_next_step() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["checkout", "-b", "feature"]);
+  std::fs::write(temp_dir.path().join("feature.txt"), "feature").unwrap();
+  git(&["add", "feature.txt"]);
+  git(&["commit", "-m", "Add feature"]);
+  git(&["push", "-u", "origin", "feature"]);
+  git(&["commit", "--allow-empty", "-m", "Polish feature"]);
+  git(&["checkout", "main"]);
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("-s")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "feature: 1 ahead of origin/feature",
+    ))
+    .stdout(predicate::str::contains("Polish feature"))
+    .stdout(predicate::str::contains("push to origin/feature"));
+}
+
+#[test]
+fn test_json_output() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["checkout", "-b", "feature"]);
+  std::fs::write(temp_dir.path().join("feature.txt"), "feature").unwrap();
+  git(&["add", "feature.txt"]);
+  git(&["commit", "-m", "Add feature"]);
+  git(&["push", "-u", "origin", "feature"]);
+  git(&["commit", "--allow-empty", "-m", "Polish feature"]);
+  git(&["checkout", "main"]);
+  git(&["branch", "merged-branch"]);
+
+  // Listing doesn't check anything out
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("--json")
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(listing["current"], "main");
+  let feature = listing["branches"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .find(|branch| branch["name"] == "feature")
+    .unwrap();
+  assert_eq!(feature["current"], false);
+  assert_eq!(feature["upstream"], "origin/feature");
+  assert_eq!(feature["tracking"]["upstream_ahead"], 1);
+  assert_eq!(feature["merge_state"]["state"], "unpushed");
+
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["-s", "--json"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let sync: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  let statuses = sync["statuses"].as_array().unwrap();
+  let feature = statuses
+    .iter()
+    .find(|status| status["branch"] == "feature")
+    .unwrap();
+  assert_eq!(feature["status"], "ahead");
+  assert_eq!(feature["subject"], "Polish feature");
+  assert_eq!(feature["suggested_action"]["action"], "push");
+  assert_eq!(feature["suggested_action"]["remote"], "origin/feature");
+  assert_eq!(sync["deleted"], serde_json::json!(["merged-branch"]));
+
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["worktree", "list", "--json"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let worktrees: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(worktrees["worktrees"][0]["branch"], "main");
+  assert_eq!(worktrees["worktrees"][0]["is_main"], true);
+}
+
+#[test]
+fn test_list_filters() {
+  let temp_dir = setup_git_repo();
+
+  let git = git_in(&temp_dir);
+
+  git(&["branch", "feature/merged"]);
+  git(&["checkout", "-b", "feature/wip"]);
+  std::fs::write(temp_dir.path().join("wip.txt"), "wip").unwrap();
+  git(&["add", "wip.txt"]);
+  git(&["commit", "-m", "Work in progress"]);
+  git(&["checkout", "-b", "other", "main"]);
+  git(&["checkout", "main"]);
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["list", "feature/*", "--merged"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("feature/merged"))
+    .stdout(predicate::str::contains("feature/wip").not())
+    .stdout(predicate::str::contains("other").not());
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["list", "--no-merged"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("feature/wip"))
+    .stdout(predicate::str::contains("1 unpushed"))
+    .stdout(predicate::str::contains("feature/merged").not());
+}
+
+#[test]
+fn test_list_merge_states() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let git = git_in(&temp_dir);
+  let commit = |file: &str| {
+    std::fs::write(temp_dir.path().join(file), file).unwrap();
+    git(&["add", file]);
+    git(&["commit", "-m", file]);
+  };
+
+  git(&["push", "-u", "origin", "main"]);
+  git(&["branch", "merged"]);
+  git(&["checkout", "-b", "picked"]);
+  commit("picked.txt");
+  git(&["checkout", "-b", "pushed", "main"]);
+  commit("pushed.txt");
+  git(&["push", "origin", "pushed"]);
+  git(&["checkout", "-b", "unpushed", "main"]);
+  commit("first.txt");
+  commit("second.txt");
+  git(&["checkout", "main"]);
+  commit("main.txt");
+  git(&["cherry-pick", "picked"]);
+
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["list", "--json"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  let merge_state = |name: &str| {
+    listing["branches"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|branch| branch["name"] == name)
+      .unwrap()["merge_state"]
+      .clone()
+  };
+
+  assert_eq!(merge_state("merged")["state"], "merged");
+  assert_eq!(merge_state("picked")["state"], "patch_merged");
+  assert_eq!(merge_state("pushed")["state"], "pushed");
+  let unpushed = merge_state("unpushed");
+  assert_eq!(unpushed["state"], "unpushed");
+  let commits: Vec<&str> = unpushed["commits"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|commit| commit.as_str().unwrap())
+    .collect();
+  assert_eq!(commits.len(), 2);
+  assert!(commits[0].ends_with(" second.txt"));
+  assert!(commits[1].ends_with(" first.txt"));
+}
+
+#[test]
+fn test_layered_config() {
+  let temp_dir = setup_git_repo();
+  let config_home = TempDir::new().unwrap();
+
+  std::fs::create_dir(config_home.path().join("swagit")).unwrap();
+  std::fs::write(
+    config_home.path().join("swagit/config.toml"),
+    "mode = \"list\"\nsort = \"name\"\nprotected = [\"develop\"]\n",
+  )
+  .unwrap();
+  std::fs::write(
+    temp_dir.path().join(".swagit.toml"),
+    "protected = [\"release/*\"]\ncolumns = [\"commit\", \"subject\"]\n",
+  )
+  .unwrap();
+  StdCommand::new("git")
+    .args(["config", "swagit.sort", "checkout"])
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  let output = Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .env("XDG_CONFIG_HOME", config_home.path())
+    .args(["config", "--json"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let config: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  let settings = &config["settings"];
+  assert_eq!(settings["mode"]["value"], "list");
+  assert!(settings["mode"]["source"]
+    .as_str()
+    .unwrap()
+    .ends_with("swagit/config.toml"));
+  assert_eq!(settings["sort"]["value"], "checkout");
+  assert_eq!(settings["sort"]["source"], "git config swagit.sort");
+  assert_eq!(
+    settings["protected"]["value"],
+    serde_json::json!(["release/*"])
+  );
+  assert!(settings["protected"]["source"]
+    .as_str()
+    .unwrap()
+    .ends_with(".swagit.toml"));
+  assert_eq!(
+    settings["columns"]["value"],
+    serde_json::json!(["commit", "subject"])
+  );
+  assert_eq!(settings["theme"]["value"], "colorful");
+  assert_eq!(settings["theme"]["source"], "default");
+
+  // The default mode lists the branches instead of opening the picker
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .env("XDG_CONFIG_HOME", config_home.path())
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("BRANCH"))
+    .stderr(predicate::str::contains("Not running in a terminal").not());
+
+  std::fs::write(temp_dir.path().join(".swagit.toml"), "theme = \"neon\"\n").unwrap();
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .env("XDG_CONFIG_HOME", config_home.path())
+    .arg("config")
+    .assert()
+    .code(9)
+    .stderr(predicate::str::contains(
+      "'neon' is not one of colorful, plain",
+    ));
+}
+
+#[test]
+fn test_new_branch() {
+  let temp_dir = setup_git_repo();
+
+  let git = git_in(&temp_dir);
+
+  // Outside a terminal the branch is created from HEAD as named
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["new", "feature/login"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Created branch feature/login from HEAD",
+    ));
+  let current = git(&["branch", "--show-current"]);
+  assert_eq!(
+    String::from_utf8_lossy(&current.stdout).trim(),
+    "feature/login"
+  );
+  assert!(
+    !git(&["rev-parse", "--abbrev-ref", "feature/login@{upstream}"])
+      .status
+      .success()
+  );
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["new", "feature/login"])
+    .assert()
+    .code(11)
+    .stderr(predicate::str::contains(
+      "branch feature/login already exists",
+    ));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["new", "bad..name"])
+    .assert()
+    .code(10)
+    .stderr(predicate::str::contains(
+      "'bad..name' is not a valid branch name",
+    ));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .arg("new")
+    .assert()
+    .code(2)
+    .stderr(predicate::str::contains(
+      "a branch name is needed outside a terminal",
+    ));
+}
+
+#[test]
+fn test_rename_branch() {
+  let temp_dir = setup_git_repo();
+  let _remote_dir = setup_remote(&temp_dir, "origin");
+  let worktree_dir = TempDir::new().unwrap();
+  let worktree_path = worktree_dir.path().join("feature");
+
+  let git = git_in(&temp_dir);
+  let stdout = |args: &[&str]| {
+    String::from_utf8_lossy(&git(args).stdout)
+      .trim()
+      .to_string()
+  };
+  git(&["branch", "feature"]);
+  git(&["push", "-u", "origin", "main", "feature"]);
+  git(&[
+    "worktree",
+    "add",
+    worktree_path.to_str().unwrap(),
+    "feature",
+  ]);
+
+  // The worktree follows the branch, the upstream is left for the user
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["rename", "feature", "feature/login"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "Renamed branch feature to feature/login",
+    ))
+    .stdout(predicate::str::contains("Updated worktree"))
+    .stdout(predicate::str::contains(
+      "feature/login still tracks origin/feature",
+    ));
+  let worktree_branch = StdCommand::new("git")
+    .args(["branch", "--show-current"])
+    .current_dir(&worktree_path)
+    .output()
+    .unwrap();
+  assert_eq!(
+    String::from_utf8_lossy(&worktree_branch.stdout).trim(),
+    "feature/login"
+  );
+  assert_eq!(
+    stdout(&["rev-parse", "--abbrev-ref", "feature/login@{upstream}"]),
+    "origin/feature"
+  );
+
+  // A single name renames the current branch
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .current_dir(&temp_dir)
+    .args(["rename", "trunk"])
+    .assert()
+    .success();
+  assert_eq!(stdout(&["branch", "--show-current"]), "trunk");
+
+  for (args, code, error) in [
+    (
+      ["rename", "trunk", "bad..name"],
+      10,
+      "'bad..name' is not a valid branch name",
+    ),
+    (
+      ["rename", "trunk", "feature/login"],
+      11,
+      "branch feature/login already exists",
+    ),
+    (
+      ["rename", "missing", "other"],
+      12,
+      "no branch matches 'missing'",
+    ),
+  ] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .assert()
+      .code(code)
+      .stderr(predicate::str::contains(error));
+  }
+}
@@ -31,3 +31,96 @@ fn test_invalid_flag() {
     .assert()
     .failure();
 }
+
+#[test]
+fn test_subcommands_and_aliases() {
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .args(["sync", "--help"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("--dry-run"))
+    .stdout(predicate::str::contains("swagit {sync|--sync|-s}"));
+
+  Command::cargo_bin("swagit")
+    .unwrap()
+    .args(["-d", "--help"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("swagit {delete|--delete|-d}"));
+}
+
+#[test]
+fn test_conflicting_flags() {
+  for args in [
+    &["-d", "-s"][..],
+    &["--sync", "--delete"][..],
+    &["--print-path", "sync"][..],
+    &["sync", "--print-path"][..],
+    &["feature", "sync"][..],
+    &["sync", "--dry-run", "--interactive"][..],
+  ] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .args(args)
+      .assert()
+      .code(2)
+      .stderr(predicate::str::contains("error:"));
+  }
+}
+
+#[test]
+fn test_conflicting_commands() {
+  for (args, error) in [
+    (&["-d", "-s"][..], "'--delete' cannot be used with '--sync'"),
+    (
+      &["--sync", "--delete"][..],
+      "'--sync' cannot be used with '--delete'",
+    ),
+  ] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .args(args)
+      .assert()
+      .code(2)
+      .stderr(predicate::str::contains(error));
+  }
+}
+
+#[test]
+fn test_global_options_around_subcommands() {
+  let temp_dir = tempfile::TempDir::new().unwrap();
+  std::process::Command::new("git")
+    .arg("init")
+    .current_dir(&temp_dir)
+    .output()
+    .unwrap();
+
+  // Runs the subcommand instead of taking its name for a branch query; sync
+  // gets as far as looking for a remote
+  for args in [
+    &["--json", "-s"][..],
+    &["-s", "--json"][..],
+    &["--json", "--sync"][..],
+    &["--sync", "--json"][..],
+    &["--json", "sync"][..],
+    &["--all", "sync", "--dry-run"][..],
+  ] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .assert()
+      .code(5)
+      .stderr(predicate::str::contains("No remote repository configured"));
+  }
+  for args in [&["-a", "list"][..], &["--recent", "checkout"][..]] {
+    Command::cargo_bin("swagit")
+      .unwrap()
+      .current_dir(&temp_dir)
+      .args(args)
+      .assert()
+      .success()
+      .stderr(predicate::str::contains("no branch matches").not());
+  }
+}